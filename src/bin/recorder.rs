@@ -0,0 +1,102 @@
+use clap::{App, Arg};
+use gstreamer as gst;
+use recorder::config::ServerConfig;
+use std::net::IpAddr;
+use tracing_subscriber::EnvFilter;
+
+fn init_tracing(format: &str, level: Option<&str>) -> anyhow::Result<()> {
+    // An explicit --log-level wins over RUST_LOG.
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level)?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = match format {
+        "json" => builder.json().try_init(),
+        "compact" => builder.compact().try_init(),
+        _ => builder.pretty().try_init(),
+    };
+    result.map_err(|e| anyhow::anyhow!("Failed to initialize tracing: {e}"))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let matches = App::new("recorder")
+        .version(recorder::VERSION)
+        .about("Camera recorder with a HTTP REST control surface")
+        .arg(
+            Arg::with_name("host")
+                .short("h")
+                .long("host")
+                .value_name("HOST")
+                .help("Sets the host to connect to")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .value_name("PORT")
+                .help("Sets the port to connect to")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("Sets the TOML server config file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("Sets the log output format")
+                .possible_values(&["pretty", "compact", "json"])
+                .default_value("pretty")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .short("l")
+                .long("log-level")
+                .value_name("FILTER")
+                .help("Sets the log level or filter, e.g. 'debug' or 'recorder=trace'. Defaults to RUST_LOG or 'info'")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    init_tracing(
+        matches.value_of("log-format").unwrap_or("pretty"),
+        matches.value_of("log-level"),
+    )?;
+
+    gst::init()?;
+    gst::debug_remove_default_log_function();
+    tracing_gstreamer::integrate_events();
+
+    let mut config = match matches.value_of("config") {
+        Some(path) => ServerConfig::from_file(path)?,
+        None => ServerConfig::default(),
+    };
+    if let Some(host) = matches.value_of("host") {
+        config.host = host
+            .parse::<IpAddr>()
+            .map_err(|e| anyhow::anyhow!("Invalid host {host}: {e}"))?;
+    }
+    if let Some(port) = matches.value_of("port") {
+        config.port = port
+            .parse::<u16>()
+            .map_err(|e| anyhow::anyhow!("Invalid port {port}: {e}"))?;
+    }
+
+    tracing::info!(
+        "Recorder {} starting on {}:{}",
+        recorder::VERSION,
+        config.host,
+        config.port
+    );
+    recorder::server::start_server(config, None).await
+}
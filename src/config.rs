@@ -0,0 +1,314 @@
+use crate::recorder::errors::RecorderError;
+use crate::recorder::Config as RecorderConfig;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+/// Server configuration, read from a TOML file.
+///
+/// ```toml
+/// host = "0.0.0.0"
+/// port = 8080
+///
+/// [recorder]
+/// duration = 3600
+///
+/// [presets.lobby-cam-1080p-x265-mkv]
+/// name = "lobby"
+/// input = { name = "lobby", variant = { V4l2 = { device = "/dev/video4" } } }
+/// encoder = { name = "x265", variant = { X265 = { bitrate = 8000 } } }
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: IpAddr,
+    pub port: u16,
+    /// Recorder configuration that every request and preset is applied on top of.
+    pub recorder: RecorderConfig,
+    /// Named, partial recorder configurations. Requests select one with the
+    /// `preset` field and can override any of its fields.
+    pub presets: BTreeMap<String, Value>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8080,
+            recorder: RecorderConfig::default(),
+            presets: BTreeMap::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let config: ServerConfig = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        // Catch broken presets at startup rather than on the first request.
+        for name in config.presets.keys() {
+            config
+                .preset(name)
+                .with_context(|| format!("Invalid preset {name} in {}", path.display()))?;
+        }
+
+        Ok(config)
+    }
+
+    pub fn preset(&self, name: &str) -> Result<RecorderConfig, RecorderError> {
+        self.resolve(serde_json::json!({ "preset": name }))
+    }
+
+    /// Builds the recorder configuration for a request body. The body is
+    /// applied field by field on top of the preset it names, if any, which
+    /// is itself applied on top of the server's default recorder config.
+    pub fn resolve(&self, mut request: Value) -> Result<RecorderConfig, RecorderError> {
+        let mut config = serde_json::to_value(&self.recorder)
+            .map_err(|e| RecorderError::ConfigError(format!("Invalid default config: {e}")))?;
+
+        let preset = match request.as_object_mut() {
+            Some(request) => request.remove("preset"),
+            None => {
+                return Err(RecorderError::ConfigError(
+                    "Request body must be a JSON object".to_string(),
+                ))
+            }
+        };
+
+        match preset {
+            Some(Value::String(name)) => {
+                let preset = self
+                    .presets
+                    .get(&name)
+                    .ok_or_else(|| RecorderError::PresetNotFound(name.clone()))?;
+                merge_layer(&mut config, preset.clone());
+            }
+            Some(Value::Null) | None => {}
+            Some(preset) => {
+                return Err(RecorderError::ConfigError(format!(
+                    "preset must be a string, got {preset}"
+                )))
+            }
+        }
+        merge_layer(&mut config, request);
+
+        serde_json::from_value(config)
+            .map_err(|e| RecorderError::ConfigError(format!("Invalid recorder config: {e}")))
+    }
+}
+
+/// Merges a preset or request into the config. A single `output` object is
+/// merged into the first of the config's `outputs` and replaces the list, so
+/// `{"output": {"name": "archive"}}` keeps working on top of a default with
+/// one output. An `outputs` list replaces the list as it is.
+fn merge_layer(config: &mut Value, mut overlay: Value) {
+    let output = overlay
+        .as_object_mut()
+        .and_then(|overlay| overlay.remove("output"));
+    if let Some(output) = output {
+        let mut first = config
+            .get("outputs")
+            .and_then(|outputs| outputs.get(0))
+            .cloned()
+            .unwrap_or_else(|| Value::Object(Default::default()));
+        merge(&mut first, output);
+        overlay["outputs"] = Value::Array(vec![first]);
+    }
+    merge(config, overlay);
+}
+
+/// Fields that hold an externally tagged enum, e.g. `{"X264": {...}}`, in
+/// the input, encoder and output configs.
+const VARIANT_FIELDS: [&str; 2] = ["variant", "muxer_config"];
+
+/// Recursively merges `overlay` into `base`. Objects are merged key by key,
+/// everything else is replaced. A `VARIANT_FIELDS` field is replaced when
+/// the overlay selects a different variant, e.g. `{"X265": {...}}` on top of
+/// `{"X264": {...}}`.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing)
+                        if VARIANT_FIELDS.contains(&key.as_str())
+                            && !is_same_variant(existing, &value) =>
+                    {
+                        *existing = value
+                    }
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn is_same_variant(base: &Value, overlay: &Value) -> bool {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => base.keys().eq(overlay.keys()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::VideoEncoder;
+    use crate::input::Variant as InputVariant;
+    use crate::output::muxer::Config as MuxerConfig;
+    use crate::output::Variant as OutputVariant;
+    use serde_json::json;
+
+    fn server_config(presets: Value) -> ServerConfig {
+        ServerConfig {
+            presets: serde_json::from_value(presets).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_layer_merges_objects_key_by_key() {
+        let mut config = json!({"name": "a", "encoder": {"name": "enc", "fallback": [1, 2]}});
+        merge_layer(
+            &mut config,
+            json!({"duration": 10, "encoder": {"fallback": [3]}}),
+        );
+        assert_eq!(
+            config,
+            json!({"name": "a", "duration": 10, "encoder": {"name": "enc", "fallback": [3]}})
+        );
+    }
+
+    #[test]
+    fn merge_layer_merges_the_same_variant() {
+        let mut config = json!({"encoder": {"variant": {"X264": {"bitrate": 1, "tune": "a"}}}});
+        merge_layer(
+            &mut config,
+            json!({"encoder": {"variant": {"X264": {"bitrate": 2}}}}),
+        );
+        assert_eq!(
+            config,
+            json!({"encoder": {"variant": {"X264": {"bitrate": 2, "tune": "a"}}}})
+        );
+    }
+
+    #[test]
+    fn merge_layer_replaces_another_variant() {
+        let mut config = json!({
+            "input": {"name": "in", "variant": {"V4l2": {"device": "/dev/video0"}}},
+            "outputs": [{"variant": {"FileSink": {"muxer_config": {"Matroska": {"a": 1}}}}}],
+        });
+        merge_layer(
+            &mut config,
+            json!({
+                "input": {"variant": {"Test": {"pattern": "ball"}}},
+                "output": {"variant": {"FileSink": {"muxer_config": {"Mpeg4": {"b": 2}}}}},
+            }),
+        );
+        assert_eq!(
+            config,
+            json!({
+                "input": {"name": "in", "variant": {"Test": {"pattern": "ball"}}},
+                "outputs": [{"variant": {"FileSink": {"muxer_config": {"Mpeg4": {"b": 2}}}}}],
+            })
+        );
+    }
+
+    #[test]
+    fn merge_layer_merges_maps_with_capitalised_keys() {
+        let mut config = json!({"input": {"variant": {"V4l2": {"controls": {"Zoom": 1}}}}});
+        merge_layer(
+            &mut config,
+            json!({"input": {"variant": {"V4l2": {"controls": {"Focus": 2}}}}}),
+        );
+        assert_eq!(
+            config,
+            json!({"input": {"variant": {"V4l2": {"controls": {"Focus": 2, "Zoom": 1}}}}})
+        );
+    }
+
+    #[test]
+    fn merge_layer_merges_output_into_the_first_output() {
+        let mut config = json!({"outputs": [{"name": "a", "x": 1}, {"name": "b"}]});
+        merge_layer(&mut config, json!({"output": {"name": "c"}}));
+        assert_eq!(config, json!({"outputs": [{"name": "c", "x": 1}]}));
+
+        merge_layer(&mut config, json!({"outputs": [{"name": "d"}]}));
+        assert_eq!(config, json!({"outputs": [{"name": "d"}]}));
+    }
+
+    #[test]
+    fn resolve_applies_the_preset_then_the_request() {
+        let config = server_config(json!({
+            "hevc": {
+                "name": "lobby",
+                "duration": 60,
+                "encoder": {"variant": {"X265": {"bitrate": 8000}}},
+            },
+        }));
+        let resolved = config
+            .resolve(json!({"preset": "hevc", "duration": 30}))
+            .unwrap();
+
+        assert_eq!(resolved.name, "lobby");
+        assert_eq!(resolved.duration, Some(30));
+        match resolved.encoder.variant {
+            VideoEncoder::X265(x265) => assert_eq!(x265.bitrate, 8000),
+            variant => panic!("expected X265, got {variant:?}"),
+        }
+    }
+
+    #[test]
+    fn resolve_keeps_defaults_of_a_partial_variant() {
+        let config = server_config(json!({}));
+        let resolved = config
+            .resolve(json!({
+                "input": {"variant": {"V4l2": {"controls": {"Brightness": 10}}}},
+                "output": {"variant": {"FileSink": {"location": "/tmp/a.mkv"}}},
+            }))
+            .unwrap();
+
+        match resolved.input.variant {
+            InputVariant::V4l2(v4l2) => {
+                assert_eq!(v4l2.controls.get("Brightness"), Some(&10));
+                assert!(!v4l2.device.is_empty());
+            }
+            variant => panic!("expected V4l2, got {variant:?}"),
+        }
+        match &resolved.outputs[0].variant {
+            OutputVariant::FileSink(sink) => {
+                assert_eq!(sink.location, "/tmp/a.mkv");
+                assert!(matches!(sink.muxer_config, MuxerConfig::Matroska(_)));
+            }
+            variant => panic!("expected FileSink, got {variant:?}"),
+        }
+    }
+
+    #[test]
+    fn resolve_rejects_unknown_presets_and_bodies() {
+        let config = server_config(json!({}));
+        assert!(matches!(
+            config.resolve(json!({"preset": "missing"})),
+            Err(RecorderError::PresetNotFound(name)) if name == "missing"
+        ));
+        assert!(matches!(
+            config.resolve(json!([])),
+            Err(RecorderError::ConfigError(_))
+        ));
+        assert!(matches!(
+            config.resolve(json!({"preset": 1})),
+            Err(RecorderError::ConfigError(_))
+        ));
+    }
+}
@@ -2,9 +2,14 @@
 pub mod av1enc;
 pub mod nvh264enc;
 pub mod nvh265enc;
+pub mod vaapih264enc;
+pub mod vaapih265enc;
+pub mod vp8enc;
+pub mod vp9enc;
 pub mod x264enc;
 pub mod x265enc;
 
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::{Pipeline, PipelineSink, PipelineSrc};
 use gstreamer as gst;
 use gstreamer::prelude::*;
@@ -16,6 +21,10 @@ use x264enc::{Config as X264Config, Encoder as X264Encoder};
 pub struct Config {
     pub name: String,
     pub variant: VideoEncoder,
+    /* Encoders to try, in order, when the element `variant` needs is not
+    installed, e.g. [{"VAAPIH264": {}}, {"X264": {}}] for NVH264. */
+    #[serde(default)]
+    pub fallback: Vec<VideoEncoder>,
 }
 
 impl Default for Config {
@@ -23,18 +32,61 @@ impl Default for Config {
         Config {
             name: "encoder".to_string(),
             variant: VideoEncoder::default(),
+            fallback: vec![],
         }
     }
 }
 
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        self.variant.validate(&format!("{field}.variant"), errors);
+        for (i, fallback) in self.fallback.iter().enumerate() {
+            fallback.validate(&format!("{field}.fallback[{i}]"), errors);
+        }
+    }
+
+    /// Returns the config with `variant` replaced by the first encoder out
+    /// of `variant` and `fallback` whose element is installed.
+    pub fn resolve(&self) -> Result<Config, RecorderError> {
+        let mut unavailable = vec![];
+        for variant in std::iter::once(&self.variant).chain(self.fallback.iter()) {
+            match variant.element_name() {
+                Ok(element) if gst::ElementFactory::find(&element).is_some() => {
+                    if !unavailable.is_empty() {
+                        tracing::warn!(
+                            "Encoder {} falling back to {element}, not installed: {}",
+                            self.name,
+                            unavailable.join(", ")
+                        );
+                    }
+                    return Ok(Config {
+                        name: self.name.clone(),
+                        variant: variant.clone(),
+                        fallback: vec![],
+                    });
+                }
+                Ok(element) => unavailable.push(element),
+                Err(RecorderError::EncoderUnavailable(msg)) => unavailable.push(msg),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(RecorderError::EncoderUnavailable(unavailable.join(", ")))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[allow(non_camel_case_types)]
 pub enum VideoEncoder {
     X264(x264enc::Config),
-    NVH264,
+    NVH264(nvh264enc::NvH264Config),
+    VAAPIH264(vaapih264enc::VaapiH264Config),
     X265(x265enc::Config),
-    NVH265,
+    NVH265(nvh265enc::NvH265Config),
+    VAAPIH265(vaapih265enc::VaapiH265Config),
     AV1(av1enc::Config),
+    VP8(vp8enc::Config),
+    VP9(vp9enc::Config),
 }
 
 impl Default for VideoEncoder {
@@ -43,33 +95,120 @@ impl Default for VideoEncoder {
     }
 }
 
+impl VideoEncoder {
+    /// Name of the element the encoder is built on.
+    pub fn element_name(&self) -> Result<String, RecorderError> {
+        match self {
+            VideoEncoder::X264(_) => Ok("x264enc".to_string()),
+            VideoEncoder::NVH264(_) => Ok("nvh264enc".to_string()),
+            VideoEncoder::VAAPIH264(_) => Ok("vaapih264enc".to_string()),
+            VideoEncoder::X265(_) => Ok("x265enc".to_string()),
+            VideoEncoder::NVH265(_) => Ok("nvh265enc".to_string()),
+            VideoEncoder::VAAPIH265(_) => Ok("vaapih265enc".to_string()),
+            VideoEncoder::AV1(config) => config.element_name().map(|e| e.to_string()),
+            VideoEncoder::VP8(_) => Ok("vp8enc".to_string()),
+            VideoEncoder::VP9(_) => Ok("vp9enc".to_string()),
+        }
+    }
+
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        match self {
+            VideoEncoder::X264(c) => c.validate(&format!("{field}.X264"), errors),
+            VideoEncoder::NVH264(c) => c.validate(&format!("{field}.NVH264"), errors),
+            VideoEncoder::VAAPIH264(c) => c.validate(&format!("{field}.VAAPIH264"), errors),
+            VideoEncoder::X265(c) => c.validate(&format!("{field}.X265"), errors),
+            VideoEncoder::NVH265(c) => c.validate(&format!("{field}.NVH265"), errors),
+            VideoEncoder::VAAPIH265(c) => c.validate(&format!("{field}.VAAPIH265"), errors),
+            VideoEncoder::AV1(c) => c.validate(&format!("{field}.AV1"), errors),
+            VideoEncoder::VP8(c) => c.validate(&format!("{field}.VP8"), errors),
+            VideoEncoder::VP9(c) => c.validate(&format!("{field}.VP9"), errors),
+        }
+    }
+
+    pub fn codec(&self) -> Codec {
+        match self {
+            VideoEncoder::X264(_) | VideoEncoder::NVH264(_) | VideoEncoder::VAAPIH264(_) => {
+                Codec::H264
+            }
+            VideoEncoder::X265(_) | VideoEncoder::NVH265(_) | VideoEncoder::VAAPIH265(_) => {
+                Codec::H265
+            }
+            VideoEncoder::AV1(_) => Codec::AV1,
+            VideoEncoder::VP8(_) => Codec::VP8,
+            VideoEncoder::VP9(_) => Codec::VP9,
+        }
+    }
+}
+
+/// Compressed video format an encoder produces.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    H264,
+    H265,
+    AV1,
+    VP8,
+    VP9,
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Codec::H264 => "H.264",
+            Codec::H265 => "H.265",
+            Codec::AV1 => "AV1",
+            Codec::VP8 => "VP8",
+            Codec::VP9 => "VP9",
+        };
+
+        f.write_str(s)
+    }
+}
+
 #[derive(Debug)]
 pub enum Encoder {
     X264(x264enc::Encoder),
-    NVH264,
+    NVH264(nvh264enc::Encoder),
+    VAAPIH264(vaapih264enc::Encoder),
     X265(x265enc::Encoder),
-    NVH265,
+    NVH265(nvh265enc::Encoder),
+    VAAPIH265(vaapih265enc::Encoder),
     AV1(av1enc::Encoder),
+    VP8(vp8enc::Encoder),
+    VP9(vp9enc::Encoder),
 }
 
 impl Encoder {
+    /// Creates the encoder for `config`. `config` is expected to be
+    /// resolved, see `Config::resolve`.
     pub fn new(config: Config) -> anyhow::Result<Self> {
         match config.variant {
             VideoEncoder::X264(_) => Ok(Encoder::X264(x264enc::Encoder::new(config)?)),
-            VideoEncoder::NVH264 => Ok(Encoder::NVH264),
+            VideoEncoder::NVH264(_) => Ok(Encoder::NVH264(nvh264enc::Encoder::new(config)?)),
+            VideoEncoder::VAAPIH264(_) => {
+                Ok(Encoder::VAAPIH264(vaapih264enc::Encoder::new(config)?))
+            }
             VideoEncoder::X265(_) => Ok(Encoder::X265(x265enc::Encoder::new(config)?)),
-            VideoEncoder::NVH265 => Ok(Encoder::NVH265),
+            VideoEncoder::NVH265(_) => Ok(Encoder::NVH265(nvh265enc::Encoder::new(config)?)),
+            VideoEncoder::VAAPIH265(_) => {
+                Ok(Encoder::VAAPIH265(vaapih265enc::Encoder::new(config)?))
+            }
             VideoEncoder::AV1(_) => Ok(Encoder::AV1(av1enc::Encoder::new(config)?)),
+            VideoEncoder::VP8(_) => Ok(Encoder::VP8(vp8enc::Encoder::new(config)?)),
+            VideoEncoder::VP9(_) => Ok(Encoder::VP9(vp9enc::Encoder::new(config)?)),
         }
     }
 
     pub fn name(&self) -> String {
         match self {
             Encoder::X264(_) => "x264enc".to_string(),
-            Encoder::NVH264 => "nvh264enc".to_string(),
+            Encoder::NVH264(_) => "nvh264enc".to_string(),
+            Encoder::VAAPIH264(_) => "vaapih264enc".to_string(),
             Encoder::X265(_) => "x265enc".to_string(),
-            Encoder::NVH265 => "nvh265enc".to_string(),
-            Encoder::AV1(_) => "av1enc".to_string(),
+            Encoder::NVH265(_) => "nvh265enc".to_string(),
+            Encoder::VAAPIH265(_) => "vaapih265enc".to_string(),
+            Encoder::AV1(enc) => enc.element_name(),
+            Encoder::VP8(_) => "vp8enc".to_string(),
+            Encoder::VP9(_) => "vp9enc".to_string(),
         }
     }
 }
@@ -78,22 +217,28 @@ impl Pipeline for Encoder {
     fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
         match self {
             Encoder::X264(enc) => enc.link(&pipeline),
-            // Encoder::NVH264 => Ok(()),
+            Encoder::NVH264(enc) => enc.link(pipeline),
+            Encoder::VAAPIH264(enc) => enc.link(pipeline),
             Encoder::X265(enc) => enc.link(&pipeline),
-            // Encoder::NVH265 => Ok(()),
-            // Encoder::AV1 => Ok(()),
-            _ => todo!("Implement the rest of the encoders"),
+            Encoder::NVH265(enc) => enc.link(pipeline),
+            Encoder::VAAPIH265(enc) => enc.link(pipeline),
+            Encoder::AV1(enc) => enc.link(pipeline),
+            Encoder::VP8(enc) => enc.link(pipeline),
+            Encoder::VP9(enc) => enc.link(pipeline),
         }
     }
 
     fn unlink(&self, pipeline: &gstreamer::Pipeline) -> anyhow::Result<()> {
         match self {
             Encoder::X264(enc) => enc.unlink(&pipeline),
-            // Encoder::NVH264 => Ok(()),
+            Encoder::NVH264(enc) => enc.unlink(pipeline),
+            Encoder::VAAPIH264(enc) => enc.unlink(pipeline),
             Encoder::X265(enc) => enc.unlink(&pipeline),
-            // Encoder::NVH265 => Ok(()),
-            // Encoder::AV1 => Ok(()),
-            _ => todo!("Implement the rest of the encoders"),
+            Encoder::NVH265(enc) => enc.unlink(pipeline),
+            Encoder::VAAPIH265(enc) => enc.unlink(pipeline),
+            Encoder::AV1(enc) => enc.unlink(pipeline),
+            Encoder::VP8(enc) => enc.unlink(pipeline),
+            Encoder::VP9(enc) => enc.unlink(pipeline),
         }
     }
 }
@@ -103,11 +248,14 @@ impl PipelineSink for Encoder {
         println!("Encoder::sink(): {:?}", self);
         match self {
             Encoder::X264(sink) => sink.sink(),
-            // Encoder::NVH264 => gst::Element::new("nvh264enc", Some("encoder")),
+            Encoder::NVH264(sink) => sink.sink(),
+            Encoder::VAAPIH264(sink) => sink.sink(),
             Encoder::X265(sink) => sink.sink(),
-            // Encoder::NVH265 => gst::Element::new("nvh265enc", Some("encoder")),
-            // Encoder::AV1 => gst::Element::new("av1enc", Some("encoder")),
-            _ => todo!("Implement the rest of the encoders"),
+            Encoder::NVH265(sink) => sink.sink(),
+            Encoder::VAAPIH265(sink) => sink.sink(),
+            Encoder::AV1(sink) => sink.sink(),
+            Encoder::VP8(sink) => sink.sink(),
+            Encoder::VP9(sink) => sink.sink(),
         }
     }
 }
@@ -116,11 +264,14 @@ impl PipelineSrc for Encoder {
     fn source(&self) -> gst::Element {
         match self {
             Encoder::X264(src) => src.source(),
-            // Encoder::NVH264 => gst::Element::new("nvh264enc", Some("encoder")),
+            Encoder::NVH264(src) => src.source(),
+            Encoder::VAAPIH264(src) => src.source(),
             Encoder::X265(src) => src.source(),
-            // Encoder::NVH265 => gst::Element::new("nvh265enc", Some("encoder")),
-            // Encoder::AV1 => gst::Element::new("av1enc", Some("encoder")),
-            _ => todo!("Implement the rest of the encoders"),
+            Encoder::NVH265(src) => src.source(),
+            Encoder::VAAPIH265(src) => src.source(),
+            Encoder::AV1(src) => src.source(),
+            Encoder::VP8(src) => src.source(),
+            Encoder::VP9(src) => src.source(),
         }
     }
 }
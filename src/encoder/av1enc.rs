@@ -1,22 +1,95 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 use crate::encoder::Config as EncoderConfig;
-use crate::recorder::errors::RecorderError;
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::*;
 use crate::util::{gst_create_element, gst_create_video_encoder};
 use gst::prelude::*;
 use gstreamer as gst;
 use serde::{Deserialize, Serialize};
 
+/// AV1 encoder elements in order of preference when `element` is "auto".
+pub const ELEMENTS: [&str; 3] = ["rav1enc", "svtav1enc", "aomenc"];
+
+/// Highest `bitrate` in kbit/s. rav1enc takes bit/s in a gint.
+pub const MAX_BITRATE: u32 = i32::MAX as u32 / 1000;
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 #[serde(default)]
 pub struct Config {
     pub name: String,
+    /* Encoder element to use: "auto", "rav1enc", "svtav1enc" or "aomenc".
+    "auto" picks the first one that is installed, in that order. */
+    pub element: String,
+    pub bitrate: u32, // kbit/s
+    /* Speed preset, higher is faster. Clamped to the range of the element:
+    rav1enc 0-10, svtav1enc 0-13, aomenc (cpu-used) 0-8 */
+    pub speed: u32,
+    pub key_int_max: u32,
+    pub tile_columns: u32, // log2 of the number of tile columns
+    pub tile_rows: u32,    // log2 of the number of tile rows
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             name: "av1enc".to_string(),
+            element: "auto".to_string(),
+            bitrate: 2048,
+            speed: 8,
+            key_int_max: 60,
+            tile_columns: 1,
+            tile_rows: 0,
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.element != "auto" && !ELEMENTS.contains(&self.element.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.element"),
+                format!(
+                    "unknown AV1 encoder {}, expected one of auto, {}",
+                    self.element,
+                    ELEMENTS.join(", ")
+                ),
+            ));
+        }
+        if self.bitrate == 0 {
+            errors.push(FieldError::new(
+                format!("{field}.bitrate"),
+                "must be greater than 0",
+            ));
+        } else if self.bitrate > MAX_BITRATE {
+            errors.push(FieldError::new(
+                format!("{field}.bitrate"),
+                format!("must be at most {MAX_BITRATE}"),
+            ));
+        }
+    }
+
+    /// Name of the encoder element that will be created for this config.
+    pub fn element_name(&self) -> Result<&str, RecorderError> {
+        if self.element != "auto" {
+            return match ELEMENTS.iter().find(|e| **e == self.element) {
+                Some(element) => Ok(element),
+                None => Err(RecorderError::ConfigError(format!(
+                    "Unknown AV1 encoder {}, expected one of auto, {}",
+                    self.element,
+                    ELEMENTS.join(", ")
+                ))),
+            };
+        }
+
+        match ELEMENTS
+            .iter()
+            .find(|e| gst::ElementFactory::find(e).is_some())
+        {
+            Some(element) => Ok(element),
+            None => Err(RecorderError::EncoderUnavailable(format!(
+                "AV1 needs one of {}",
+                ELEMENTS.join(", ")
+            ))),
         }
     }
 }
@@ -60,6 +133,15 @@ impl Pipeline for Encoder {
     }
 }
 
+impl Encoder {
+    pub fn element_name(&self) -> String {
+        match self.encoder.factory() {
+            Some(factory) => factory.name().to_string(),
+            None => "av1enc".to_string(),
+        }
+    }
+}
+
 impl PipelineSrc for Encoder {
     fn source(&self) -> gst::Element {
         self.av1parse.clone()
@@ -1,13 +1,143 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::encoder::Config as EncoderConfig;
+use crate::recorder::errors::{FieldError, RecorderError};
+use crate::traits::*;
+use crate::util::{gst_create_element, gst_create_video_encoder};
+use gst::prelude::*;
+use gstreamer as gst;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct Encoder {
+    config: EncoderConfig,
+
+    video_convert: gst::Element,
+    encoder: gst::Element,
+    h264parse: gst::Element,
+}
+
+impl Encoder {
+    pub fn new(config: EncoderConfig) -> anyhow::Result<Self> {
+        let video_convert = gst_create_element("videoconvert", "videoconvert0")?;
+        let encoder = gst_create_video_encoder(&config.variant)?;
+        let h264parse =
+            gst_create_element("h264parse", &format!("output_{}_h264parse", &config.name))?;
+        Ok(Encoder {
+            config,
+            video_convert,
+            encoder,
+            h264parse,
+        })
+    }
+}
+
+impl Pipeline for Encoder {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.add_many(&[&self.video_convert, &self.encoder, &self.h264parse])?;
+        gst::Element::link_many(&[&self.video_convert, &self.encoder, &self.h264parse])?;
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.remove_many(&[&self.video_convert, &self.encoder, &self.h264parse])?;
+        Ok(())
+    }
+}
+
+impl PipelineSrc for Encoder {
+    fn source(&self) -> gst::Element {
+        self.h264parse.clone()
+    }
+}
+
+impl PipelineSink for Encoder {
+    fn sink(&self) -> gst::Element {
+        self.video_convert.clone()
+    }
+}
+
+/// Named values of the nvh264enc/nvh265enc preset property.
+pub const PRESETS: [&str; 8] = [
+    "default",
+    "hp",
+    "hq",
+    "low-latency",
+    "low-latency-hq",
+    "low-latency-hp",
+    "lossless",
+    "lossless-hp",
+];
+
+/// Named values of the nvh264enc/nvh265enc rc-mode property.
+pub const RC_MODES: [&str; 8] = [
+    "default",
+    "constqp",
+    "cbr",
+    "vbr",
+    "vbr-minqp",
+    "cbr-ld-hq",
+    "cbr-hq",
+    "vbr-hq",
+];
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 #[serde(default)]
 pub struct NvH264Config {
+    //Use gst-inspect-1.0 nvh264enc to see all options
     pub name: String,
+    pub bitrate: u32, // kbit/s
+
+    /* Settings for preset
+    Enum "GstNvPreset" Default: 0, "default"
+    (0): default, (1): hp, (2): hq, (3): low-latency, (4): low-latency-hq,
+    (5): low-latency-hp, (6): lossless, (7): lossless-hp
+    */
+    pub preset: String,
+
+    /* Settings for rc_mode
+    Enum "GstNvRCMode" Default: 0, "default"
+    (0): default, (1): constqp, (2): cbr, (3): vbr, (4): vbr-minqp,
+    (5): cbr-ld-hq, (6): cbr-hq, (7): vbr-hq
+    */
+    pub rc_mode: String,
+    pub gop_size: i32, // -1 for infinite
+    pub zerolatency: bool,
 }
 
 impl Default for NvH264Config {
     fn default() -> Self {
         NvH264Config {
-            name: "nvh265enc".to_string(),
+            name: "nvh264enc".to_string(),
+            bitrate: 2048,
+            preset: "low-latency-hq".to_string(),
+            rc_mode: "cbr".to_string(),
+            gop_size: 60,
+            zerolatency: true,
+        }
+    }
+}
+
+impl NvH264Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if !PRESETS.contains(&self.preset.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.preset"),
+                format!(
+                    "unknown preset {}, expected one of {}",
+                    self.preset,
+                    PRESETS.join(", ")
+                ),
+            ));
+        }
+        if !RC_MODES.contains(&self.rc_mode.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.rc_mode"),
+                format!(
+                    "unknown rc mode {}, expected one of {}",
+                    self.rc_mode,
+                    RC_MODES.join(", ")
+                ),
+            ));
         }
     }
 }
@@ -1,13 +1,120 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::encoder::nvh264enc::{PRESETS, RC_MODES};
+use crate::encoder::Config as EncoderConfig;
+use crate::recorder::errors::{FieldError, RecorderError};
+use crate::traits::*;
+use crate::util::{gst_create_element, gst_create_video_encoder};
+use gst::prelude::*;
+use gstreamer as gst;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct Encoder {
+    config: EncoderConfig,
+
+    video_convert: gst::Element,
+    encoder: gst::Element,
+    h265parse: gst::Element,
+}
+
+impl Encoder {
+    pub fn new(config: EncoderConfig) -> anyhow::Result<Self> {
+        let video_convert = gst_create_element("videoconvert", "videoconvert0")?;
+        let encoder = gst_create_video_encoder(&config.variant)?;
+        let h265parse =
+            gst_create_element("h265parse", &format!("output_{}_h265parse", &config.name))?;
+        Ok(Encoder {
+            config,
+            video_convert,
+            encoder,
+            h265parse,
+        })
+    }
+}
+
+impl Pipeline for Encoder {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.add_many(&[&self.video_convert, &self.encoder, &self.h265parse])?;
+        gst::Element::link_many(&[&self.video_convert, &self.encoder, &self.h265parse])?;
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.remove_many(&[&self.video_convert, &self.encoder, &self.h265parse])?;
+        Ok(())
+    }
+}
+
+impl PipelineSrc for Encoder {
+    fn source(&self) -> gst::Element {
+        self.h265parse.clone()
+    }
+}
+
+impl PipelineSink for Encoder {
+    fn sink(&self) -> gst::Element {
+        self.video_convert.clone()
+    }
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 #[serde(default)]
 pub struct NvH265Config {
+    //Use gst-inspect-1.0 nvh265enc to see all options
     pub name: String,
+    pub bitrate: u32, // kbit/s
+
+    /* Settings for preset
+    Enum "GstNvPreset" Default: 0, "default"
+    (0): default, (1): hp, (2): hq, (3): low-latency, (4): low-latency-hq,
+    (5): low-latency-hp, (6): lossless, (7): lossless-hp
+    */
+    pub preset: String,
+
+    /* Settings for rc_mode
+    Enum "GstNvRCMode" Default: 0, "default"
+    (0): default, (1): constqp, (2): cbr, (3): vbr, (4): vbr-minqp,
+    (5): cbr-ld-hq, (6): cbr-hq, (7): vbr-hq
+    */
+    pub rc_mode: String,
+    pub gop_size: i32, // -1 for infinite
+    pub zerolatency: bool,
 }
 
 impl Default for NvH265Config {
     fn default() -> Self {
         NvH265Config {
             name: "nvh265enc".to_string(),
+            bitrate: 2048,
+            preset: "low-latency-hq".to_string(),
+            rc_mode: "cbr".to_string(),
+            gop_size: 60,
+            zerolatency: true,
+        }
+    }
+}
+
+impl NvH265Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if !PRESETS.contains(&self.preset.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.preset"),
+                format!(
+                    "unknown preset {}, expected one of {}",
+                    self.preset,
+                    PRESETS.join(", ")
+                ),
+            ));
+        }
+        if !RC_MODES.contains(&self.rc_mode.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.rc_mode"),
+                format!(
+                    "unknown rc mode {}, expected one of {}",
+                    self.rc_mode,
+                    RC_MODES.join(", ")
+                ),
+            ));
         }
     }
 }
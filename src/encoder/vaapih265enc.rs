@@ -0,0 +1,99 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::encoder::Config as EncoderConfig;
+use crate::recorder::errors::{FieldError, RecorderError};
+use crate::traits::*;
+use crate::util::{gst_create_element, gst_create_video_encoder};
+use gst::prelude::*;
+use gstreamer as gst;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct Encoder {
+    config: EncoderConfig,
+
+    video_convert: gst::Element,
+    encoder: gst::Element,
+    h265parse: gst::Element,
+}
+
+impl Encoder {
+    pub fn new(config: EncoderConfig) -> anyhow::Result<Self> {
+        let video_convert = gst_create_element("videoconvert", "videoconvert0")?;
+        let encoder = gst_create_video_encoder(&config.variant)?;
+        let h265parse =
+            gst_create_element("h265parse", &format!("output_{}_h265parse", &config.name))?;
+        Ok(Encoder {
+            config,
+            video_convert,
+            encoder,
+            h265parse,
+        })
+    }
+}
+
+impl Pipeline for Encoder {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.add_many(&[&self.video_convert, &self.encoder, &self.h265parse])?;
+        gst::Element::link_many(&[&self.video_convert, &self.encoder, &self.h265parse])?;
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.remove_many(&[&self.video_convert, &self.encoder, &self.h265parse])?;
+        Ok(())
+    }
+}
+
+impl PipelineSrc for Encoder {
+    fn source(&self) -> gst::Element {
+        self.h265parse.clone()
+    }
+}
+
+impl PipelineSink for Encoder {
+    fn sink(&self) -> gst::Element {
+        self.video_convert.clone()
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct VaapiH265Config {
+    //Use gst-inspect-1.0 vaapih265enc to see all options
+    pub name: String,
+    pub bitrate: u32, // kbit/s
+
+    /* Settings for rate_control
+    Enum "GstVaapiRateControl" Default: 2, "cbr"
+    (1): cqp, (2): cbr, (4): vbr, (5): vbr_constrained, (9): qvbr
+    Not every driver supports every mode.
+    */
+    pub rate_control: String,
+    pub keyframe_period: u32,
+    pub quality_level: u32, // 1 (best quality) - 7 (fastest)
+    pub max_bframes: u32,
+}
+
+impl Default for VaapiH265Config {
+    fn default() -> Self {
+        VaapiH265Config {
+            name: "vaapih265enc".to_string(),
+            bitrate: 2048,
+            rate_control: "cbr".to_string(),
+            keyframe_period: 60,
+            quality_level: 4,
+            max_bframes: 0,
+        }
+    }
+}
+
+impl VaapiH265Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if !(1..=7).contains(&self.quality_level) {
+            errors.push(FieldError::new(
+                format!("{field}.quality_level"),
+                "must be between 1 and 7",
+            ));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::encoder::Config as EncoderConfig;
+use crate::recorder::errors::{FieldError, RecorderError};
+use crate::traits::*;
+use crate::util::{gst_create_element, gst_create_video_encoder};
+use gst::prelude::*;
+use gstreamer as gst;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct Encoder {
+    config: EncoderConfig,
+
+    video_convert: gst::Element,
+    encoder: gst::Element,
+}
+
+impl Encoder {
+    pub fn new(config: EncoderConfig) -> anyhow::Result<Self> {
+        let video_convert = gst_create_element("videoconvert", "videoconvert0")?;
+        let encoder = gst_create_video_encoder(&config.variant)?;
+        Ok(Encoder {
+            config,
+            video_convert,
+            encoder,
+        })
+    }
+}
+
+impl Pipeline for Encoder {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.add_many(&[&self.video_convert, &self.encoder])?;
+        gst::Element::link_many(&[&self.video_convert, &self.encoder])?;
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.remove_many(&[&self.video_convert, &self.encoder])?;
+        Ok(())
+    }
+}
+
+impl PipelineSrc for Encoder {
+    fn source(&self) -> gst::Element {
+        // The muxers take the VP9 encoder output as is, no parser needed.
+        self.encoder.clone()
+    }
+}
+
+impl PipelineSink for Encoder {
+    fn sink(&self) -> gst::Element {
+        self.video_convert.clone()
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    //Use gst-inspect-1.0 vp9enc to see all options
+    pub name: String,
+    pub target_bitrate: i32, // bit/s
+    pub deadline: i64, // Time in microseconds to spend per frame. 0: best quality, 1: realtime
+    pub cpu_used: i32, // Higher is faster and lower quality
+    pub keyframe_max_dist: i32,
+    pub threads: i32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            name: "vp9enc".to_string(),
+            target_bitrate: 2048000,
+            deadline: 1,
+            cpu_used: 8,
+            keyframe_max_dist: 60,
+            threads: 0,
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.target_bitrate <= 0 {
+            errors.push(FieldError::new(
+                format!("{field}.target_bitrate"),
+                "must be greater than 0",
+            ));
+        }
+        if self.deadline < 0 {
+            errors.push(FieldError::new(
+                format!("{field}.deadline"),
+                "must not be negative",
+            ));
+        }
+    }
+}
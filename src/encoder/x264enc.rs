@@ -1,6 +1,6 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 use crate::encoder::Config as EncoderConfig;
-use crate::recorder::errors::RecorderError;
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::*;
 use crate::util::{gst_create_element, gst_create_video_encoder};
 use anyhow::Ok;
@@ -59,6 +59,20 @@ impl PipelineSink for Encoder {
     }
 }
 
+/// Named values of the x264enc/x265enc speed-preset property.
+pub const SPEED_PRESETS: [&str; 10] = [
+    "ultrafast",
+    "superfast",
+    "veryfast",
+    "faster",
+    "fast",
+    "medium",
+    "slow",
+    "slower",
+    "veryslow",
+    "placebo",
+];
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 #[serde(default)]
 pub struct Config {
@@ -225,3 +239,24 @@ impl Default for Config {
         }
     }
 }
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.bitrate == 0 {
+            errors.push(FieldError::new(
+                format!("{field}.bitrate"),
+                "must be greater than 0",
+            ));
+        }
+        if !SPEED_PRESETS.contains(&self.speed_preset.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.speed_preset"),
+                format!(
+                    "unknown speed preset {}, expected one of {}",
+                    self.speed_preset,
+                    SPEED_PRESETS.join(", ")
+                ),
+            ));
+        }
+    }
+}
@@ -1,6 +1,7 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::encoder::x264enc::SPEED_PRESETS;
 use crate::encoder::Config as EncoderConfig;
-use crate::recorder::errors::RecorderError;
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::*;
 use crate::util::{gst_create_element, gst_create_video_encoder};
 use gst::prelude::*;
@@ -82,3 +83,24 @@ impl Default for Config {
         }
     }
 }
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.bitrate == 0 {
+            errors.push(FieldError::new(
+                format!("{field}.bitrate"),
+                "must be greater than 0",
+            ));
+        }
+        if !SPEED_PRESETS.contains(&self.speed_preset.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.speed_preset"),
+                format!(
+                    "unknown speed preset {}, expected one of {}",
+                    self.speed_preset,
+                    SPEED_PRESETS.join(", ")
+                ),
+            ));
+        }
+    }
+}
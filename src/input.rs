@@ -1,13 +1,19 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+pub mod devices;
 mod test;
+mod uri;
 mod v4l2;
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::{Pipeline, PipelineSrc};
 use anyhow::Result;
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 
+pub use v4l2::Control;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub name: String,
@@ -24,6 +30,24 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        match &self.variant {
+            Variant::Test(c) => c.validate(&format!("{field}.variant.Test"), errors),
+            Variant::V4l2(c) => c.validate(&format!("{field}.variant.V4l2"), errors),
+            Variant::Uri(c) => c.validate(&format!("{field}.variant.Uri"), errors),
+        }
+    }
+
+    /// The camera the input records from, if it is one.
+    pub fn device(&self) -> Option<&str> {
+        match &self.variant {
+            Variant::Test(_) | Variant::Uri(_) => None,
+            Variant::V4l2(c) => Some(&c.device),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub enum InputStats {
     Test(test::Stats),
@@ -34,6 +58,7 @@ pub enum InputStats {
 pub enum Variant {
     Test(test::Config),
     V4l2(v4l2::Config),
+    Uri(uri::Config),
 }
 
 impl Default for Variant {
@@ -47,6 +72,7 @@ impl Default for Variant {
 pub enum Input {
     Test(test::Test),
     V4l2(v4l2::V4l2),
+    Uri(uri::Uri),
 }
 
 impl Input {
@@ -54,6 +80,42 @@ impl Input {
         match config.variant {
             Variant::Test(c) => Ok(Input::Test(test::Test::new(config.name, c)?)),
             Variant::V4l2(c) => Ok(Input::V4l2(v4l2::V4l2::new("v4l2src".to_string(), c)?)),
+            Variant::Uri(c) => Ok(Input::Uri(uri::Uri::new(config.name, c)?)),
+        }
+    }
+}
+
+impl Input {
+    /// The camera controls of the input, see `v4l2::V4l2::controls`.
+    pub fn controls(&self) -> Result<Vec<Control>, RecorderError> {
+        match self {
+            Input::Test(_) | Input::Uri(_) => Err(RecorderError::ConfigError(
+                "Only a V4l2 input has controls".to_string(),
+            )),
+            Input::V4l2(input) => input.controls(),
+        }
+    }
+
+    pub fn set_controls(
+        &mut self,
+        values: &BTreeMap<String, i32>,
+    ) -> Result<Vec<Control>, RecorderError> {
+        match self {
+            Input::Test(_) | Input::Uri(_) => Err(RecorderError::ConfigError(
+                "Only a V4l2 input has controls".to_string(),
+            )),
+            Input::V4l2(input) => input.set_controls(values),
+        }
+    }
+}
+
+impl Input {
+    /// Called for the SEGMENT_DONE message of the pipeline, which only a
+    /// looping Uri input causes.
+    pub fn segment_done(&self) -> anyhow::Result<()> {
+        match self {
+            Input::Uri(input) => input.segment_done(),
+            _ => Ok(()),
         }
     }
 }
@@ -63,6 +125,7 @@ impl Pipeline for Input {
         match self {
             Input::Test(input) => input.link(pipeline),
             Input::V4l2(input) => input.link(pipeline),
+            Input::Uri(input) => input.link(pipeline),
         }
     }
 
@@ -70,6 +133,7 @@ impl Pipeline for Input {
         match self {
             Input::Test(input) => input.unlink(pipeline),
             Input::V4l2(input) => input.unlink(pipeline),
+            Input::Uri(input) => input.unlink(pipeline),
         }
     }
 }
@@ -79,6 +143,7 @@ impl PipelineSrc for Input {
         match self {
             Input::Test(input) => input.source(),
             Input::V4l2(input) => input.source(),
+            Input::Uri(input) => input.source(),
         }
     }
 }
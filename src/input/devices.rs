@@ -0,0 +1,111 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::recorder::errors::RecorderError;
+use crate::util::gst_describe_caps;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use serde::Serialize;
+
+/// A camera as reported by the device monitor.
+#[derive(Debug, Serialize, Clone)]
+pub struct Device {
+    /// Use as `input::v4l2::Config::device`.
+    pub path: Option<String>,
+    pub name: String,
+    pub driver: Option<String>,
+    /// Every format, size and framerate the camera supports.
+    pub caps: Vec<String>,
+    pub in_use: bool,
+    /// Session that is recording from the camera. `None` for `/start`, or if
+    /// it isn't in use.
+    pub session: Option<String>,
+}
+
+/// A device that is being recorded from, and by which session.
+pub struct InUse {
+    pub path: String,
+    pub session: Option<String>,
+}
+
+/// Keeps track of the connected cameras. The monitor runs for the lifetime
+/// of the server, so the list follows cameras being plugged in and out.
+#[derive(Debug)]
+pub struct Devices {
+    monitor: gst::DeviceMonitor,
+    started: bool,
+}
+
+impl Devices {
+    pub fn new() -> Self {
+        let monitor = gst::DeviceMonitor::new();
+        monitor.add_filter(Some("Video/Source"), None);
+        let started = match monitor.start() {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!("Failed to start device monitor: {e}");
+                false
+            }
+        };
+
+        // Nobody else reads the monitor's bus, the messages would pile up.
+        let bus = monitor.bus();
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                use gst::MessageView;
+                match msg.view() {
+                    MessageView::DeviceAdded(added) => {
+                        tracing::info!("Camera added: {}", added.device().display_name());
+                    }
+                    MessageView::DeviceRemoved(removed) => {
+                        tracing::info!("Camera removed: {}", removed.device().display_name());
+                    }
+                    _ => (),
+                }
+            }
+        });
+
+        Devices { monitor, started }
+    }
+
+    /// The connected cameras, marking the ones in `in_use`.
+    pub fn list(&self, in_use: &[InUse]) -> Result<Vec<Device>, RecorderError> {
+        if !self.started {
+            return Err(RecorderError::InputError(
+                "Device monitor is not running".to_string(),
+            ));
+        }
+
+        Ok(self
+            .monitor
+            .devices()
+            .iter()
+            .map(|device| {
+                let properties = device.properties();
+                let property =
+                    |name: &str| properties.as_ref().and_then(|p| p.get::<String>(name).ok());
+                let path = property("device.path").or_else(|| property("api.v4l2.path"));
+                let user = in_use
+                    .iter()
+                    .find(|u| path.as_deref() == Some(u.path.as_str()));
+                Device {
+                    name: device.display_name().to_string(),
+                    driver: property("v4l2.device.driver"),
+                    caps: device
+                        .caps()
+                        .map(|caps| gst_describe_caps(&caps))
+                        .unwrap_or_default(),
+                    in_use: user.is_some(),
+                    session: user.and_then(|u| u.session.clone()),
+                    path,
+                }
+            })
+            .collect())
+    }
+}
+
+impl Drop for Devices {
+    fn drop(&mut self) {
+        if self.started {
+            self.monitor.stop();
+        }
+    }
+}
@@ -1,7 +1,7 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
-use crate::recorder::errors::RecorderError;
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::{Pipeline, PipelineSrc};
-use crate::util::gst_create_element;
+use crate::util::{gst_create_element, gst_set_property, gst_set_property_from_str};
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use serde::Serialize;
@@ -15,13 +15,130 @@ pub struct Stats {
     video_queue_overrun_count: u64,
 }
 
+/// Nicks of the videotestsrc pattern property.
+pub const PATTERNS: [&str; 26] = [
+    "smpte",
+    "snow",
+    "black",
+    "white",
+    "red",
+    "green",
+    "blue",
+    "checkers-1",
+    "checkers-2",
+    "checkers-4",
+    "checkers-8",
+    "circular",
+    "blink",
+    "smpte75",
+    "zone-plate",
+    "gamut",
+    "chroma-zone-plate",
+    "solid-color",
+    "ball",
+    "smpte100",
+    "bar",
+    "pinwheel",
+    "spokes",
+    "gradient",
+    "colors",
+    "smpte-rp-219",
+];
+
+/// Drawn on top of the pattern, so it is obvious in a recording whether
+/// frames were dropped or repeated.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    /// A ball moving across the picture.
+    Ball,
+    /// The running time of each frame.
+    Timestamp,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(default)]
-pub struct Config {}
+pub struct Config {
+    pub pattern: String, // One of PATTERNS
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32, // Frames per second
+    /* Number of frames before the input sends EOS, which ends the recording
+    like a stop request. None for no end. */
+    pub num_buffers: Option<u32>,
+    /* Produce frames at the framerate. A non-live source runs as fast as the
+    encoder can take the frames. */
+    pub is_live: bool,
+    pub foreground_color: u32, // ARGB, used by e.g. "solid-color" and the ball
+    pub overlay: Option<Overlay>,
+}
 
 impl Default for Config {
     fn default() -> Self {
-        Self {}
+        Self {
+            pattern: "smpte".to_string(),
+            width: 1280,
+            height: 720,
+            framerate: 30,
+            num_buffers: None,
+            is_live: true,
+            foreground_color: 0xffffffff,
+            overlay: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if !PATTERNS.contains(&self.pattern.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.pattern"),
+                format!(
+                    "unknown pattern {}, expected one of {}",
+                    self.pattern,
+                    PATTERNS.join(", ")
+                ),
+            ));
+        }
+        for (name, value) in [
+            ("width", self.width),
+            ("height", self.height),
+            ("framerate", self.framerate),
+        ] {
+            if value == 0 {
+                errors.push(FieldError::new(
+                    format!("{field}.{name}"),
+                    "must be greater than 0",
+                ));
+            } else if value > i32::MAX as u32 {
+                errors.push(FieldError::new(
+                    format!("{field}.{name}"),
+                    format!("must be at most {}", i32::MAX),
+                ));
+            }
+        }
+        if self.num_buffers == Some(0) {
+            errors.push(FieldError::new(
+                format!("{field}.num_buffers"),
+                "must be greater than 0",
+            ));
+        } else if matches!(self.num_buffers, Some(n) if n > i32::MAX as u32) {
+            errors.push(FieldError::new(
+                format!("{field}.num_buffers"),
+                format!("must be at most {}", i32::MAX),
+            ));
+        }
+    }
+
+    /// Raw video caps with the configured size and framerate.
+    fn caps(&self, format: Option<&str>) -> gst::Caps {
+        let mut caps = gst::Caps::builder("video/x-raw")
+            .field("width", self.width as i32)
+            .field("height", self.height as i32)
+            .field("framerate", gst::Fraction::new(self.framerate as i32, 1));
+        if let Some(format) = format {
+            caps = caps.field("format", format);
+        }
+        caps.build()
     }
 }
 
@@ -32,6 +149,12 @@ pub struct Test {
     stats: Arc<Mutex<Stats>>,
 
     video: gst::Element,
+    caps_filter: gst::Element,
+    video_convert: gst::Element,
+    /// Between `video_convert` and `video_queue`, empty without an overlay.
+    overlay: Vec<gst::Element>,
+    /// Source of the ball overlay, linked into the compositor in `overlay`.
+    ball: Vec<gst::Element>,
     video_queue: gst::Element,
 }
 
@@ -41,9 +164,11 @@ impl Test {
 
         let stats = Arc::new(Mutex::new(Stats::default()));
 
-        let video = gst_create_element("videotestsrc", &format!("input_{}_videotestsrc", &name))?;
-        video.set_property("is-live", true);
-        video.set_property_from_str("pattern", "smpte");
+        let video = Self::create_source(&config, &format!("input_{}_videotestsrc", &name))?;
+        gst_set_property_from_str(&video, "pattern", &config.pattern)?;
+
+        let caps_filter = gst_create_element("capsfilter", &format!("input_{}_caps", &name))?;
+        caps_filter.set_property("caps", config.caps(None));
 
         let video_queue = gst_create_element("queue", &format!("input_{}_video_queue", &name))?;
         let stat = stats.clone();
@@ -55,15 +180,67 @@ impl Test {
         let video_convert =
             gst_create_element("videoconvert", &format!("input_{}_video_convert", &name))?;
 
+        let (overlay, ball) = match config.overlay {
+            None => (vec![], vec![]),
+            Some(Overlay::Timestamp) => {
+                let time_overlay =
+                    gst_create_element("timeoverlay", &format!("input_{}_timeoverlay", &name))?;
+                (vec![time_overlay], vec![])
+            }
+            Some(Overlay::Ball) => {
+                // The ball is drawn on a transparent background and mixed on
+                // top of the pattern.
+                let ball = Self::create_source(&config, &format!("input_{}_ball", &name))?;
+                gst_set_property_from_str(&ball, "pattern", "ball")?;
+                gst_set_property(&ball, "background-color", 0x00000000u32)?;
+                let ball_caps =
+                    gst_create_element("capsfilter", &format!("input_{}_ball_caps", &name))?;
+                ball_caps.set_property("caps", config.caps(Some("BGRA")));
+                let compositor =
+                    gst_create_element("compositor", &format!("input_{}_compositor", &name))?;
+                (vec![compositor], vec![ball, ball_caps])
+            }
+        };
+
         Ok(Self {
             name,
             config,
             stats,
             video,
+            caps_filter,
+            video_convert,
+            overlay,
+            ball,
             video_queue,
         })
     }
 
+    /// A videotestsrc with the settings shared by the pattern and the ball.
+    fn create_source(config: &Config, name: &str) -> anyhow::Result<gst::Element> {
+        let source = gst_create_element("videotestsrc", name)?;
+        source.set_property("is-live", config.is_live);
+        gst_set_property(&source, "foreground-color", config.foreground_color)?;
+        if let Some(num_buffers) = config.num_buffers {
+            gst_set_property(&source, "num-buffers", num_buffers as i32)?;
+        }
+        Ok(source)
+    }
+
+    /// The elements from the pattern to the queue, in order.
+    fn chain(&self) -> Vec<&gst::Element> {
+        [&self.video, &self.caps_filter, &self.video_convert]
+            .into_iter()
+            .chain(self.overlay.iter())
+            .chain(std::iter::once(&self.video_queue))
+            .collect()
+    }
+
+    fn elements(&self) -> Vec<&gst::Element> {
+        let mut elements = self.chain();
+        elements.extend(self.ball.iter());
+        elements
+    }
+
     pub fn _name(&self) -> &str {
         &self.name
     }
@@ -78,28 +255,31 @@ impl Drop for Test {
 impl Pipeline for Test {
     fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
         tracing::info!("Linking Test input {}", &self.name);
-        pipeline
-            .add_many(&[&self.video, &self.video_queue])
-            .map_err(|_| {
-                RecorderError::ElementError(format!(
-                    "Error adding {} elements to pipeline",
-                    self.name
-                ))
-            })?;
+        pipeline.add_many(&self.elements()).map_err(|_| {
+            RecorderError::ElementError(format!("Error adding {} elements to pipeline", self.name))
+        })?;
 
-        gst::Element::link_many(&[&self.video, &self.video_queue]).map_err(|_| {
+        gst::Element::link_many(&self.chain()).map_err(|_| {
             RecorderError::ElementError(format!("Error linking {} video elements", self.name))
         })?;
 
+        // Linked after the pattern, so the ball gets the upper pad of the
+        // compositor.
+        if let Some(compositor) = self.overlay.first().filter(|_| !self.ball.is_empty()) {
+            let mut ball = self.ball.iter().collect::<Vec<_>>();
+            ball.push(compositor);
+            gst::Element::link_many(&ball).map_err(|_| {
+                RecorderError::ElementError(format!("Error linking {} ball overlay", self.name))
+            })?;
+        }
+
         Ok(())
     }
 
     fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
-        pipeline
-            .remove_many(&[&self.video, &self.video_queue])
-            .map_err(|_| {
-                RecorderError::ElementError(format!("Error unlinking {} elements", self.name))
-            })?;
+        pipeline.remove_many(&self.elements()).map_err(|_| {
+            RecorderError::ElementError(format!("Error unlinking {} elements", self.name))
+        })?;
 
         Ok(())
     }
@@ -0,0 +1,312 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::recorder::errors::{FieldError, RecorderError};
+use crate::traits::{Pipeline, PipelineSrc};
+use crate::util::gst_create_element;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub uri: String, // A URI, e.g. "file:///videos/a.mkv", or a path
+    /* Start over at `start_ms` at the end of the media, instead of ending the
+    recording. */
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    pub start_ms: u64, // Position in the media to start at
+    /* Play the media at its own speed, like a camera. Otherwise it is read as
+    fast as the encoder can take it. */
+    pub realtime: bool,
+    pub video_stream: u32, // Index of the video stream to use, 0 for the first
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            uri: String::new(),
+            looping: false,
+            start_ms: 0,
+            realtime: true,
+            video_stream: 0,
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.uri.is_empty() {
+            errors.push(FieldError::new(format!("{field}.uri"), "must not be empty"));
+        } else if !is_uri(&self.uri) && !Path::new(&self.uri).is_file() {
+            errors.push(FieldError::new(
+                format!("{field}.uri"),
+                format!("{} is neither a URI nor an existing file", self.uri),
+            ));
+        }
+    }
+
+    /// `uri` as a URI, a path is turned into a file:// URI.
+    fn resolve_uri(&self) -> anyhow::Result<String> {
+        if is_uri(&self.uri) {
+            return Ok(self.uri.clone());
+        }
+        let path = std::fs::canonicalize(&self.uri)
+            .map_err(|e| RecorderError::InputError(format!("Failed to open {}: {e}", self.uri)))?;
+        let uri = gst::glib::filename_to_uri(&path, None)
+            .map_err(|e| RecorderError::InputError(format!("Invalid path {}: {e}", self.uri)))?;
+        Ok(uri.to_string())
+    }
+
+    fn start(&self) -> gst::ClockTime {
+        gst::ClockTime::from_mseconds(self.start_ms)
+    }
+}
+
+/// Whether `uri` starts with a scheme, like "file://" or "rtsp://".
+fn is_uri(uri: &str) -> bool {
+    match uri.split_once("://") {
+        Some((scheme, _)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+/// Decodes a file or stream with uridecodebin. The selected video stream
+/// feeds the encoder, all other streams end in fakesinks.
+#[derive(Debug)]
+pub struct Uri {
+    name: String,
+    config: Config,
+
+    decode: gst::Element,
+    video_convert: gst::Element,
+    clock_sync: gst::Element,
+    video_queue: gst::Element,
+    video_sink: gst::Pad,
+}
+
+impl Uri {
+    pub fn new(name: String, config: Config) -> anyhow::Result<Self> {
+        tracing::info!("Creating Uri input {}", &name);
+
+        let decode = gst_create_element("uridecodebin", &format!("input_{}_decode", &name))?;
+        decode.set_property("uri", config.resolve_uri()?);
+
+        let video_convert =
+            gst_create_element("videoconvert", &format!("input_{}_video_convert", &name))?;
+        let clock_sync = gst_create_element("clocksync", &format!("input_{}_clocksync", &name))?;
+        clock_sync.set_property("sync", config.realtime);
+        let video_queue = gst_create_element("queue", &format!("input_{}_video_queue", &name))?;
+
+        let video_sink = video_convert.static_pad("sink").ok_or_else(|| {
+            RecorderError::ElementError(format!("{name} videoconvert has no sink pad"))
+        })?;
+        let video_pads = AtomicU32::new(0);
+        let pad_config = config.clone();
+        let seek_sink = video_sink.clone();
+        decode.connect_pad_added(move |decode, pad| {
+            let is_video = pad
+                .current_caps()
+                .unwrap_or_else(|| pad.query_caps(None))
+                .structure(0)
+                .map(|s| s.name().starts_with("video/"))
+                .unwrap_or(false);
+            if is_video
+                && video_pads.fetch_add(1, Ordering::SeqCst) == pad_config.video_stream
+                && !video_sink.is_linked()
+            {
+                tracing::info!("Using video stream {}", pad_config.video_stream);
+                if pad_config.start_ms > 0 || pad_config.looping {
+                    Self::seek_before_start(pad, &pad_config);
+                }
+                if let Err(e) = pad.link(&video_sink) {
+                    tracing::error!("Failed to link video stream: {e:?}");
+                }
+                return;
+            }
+            if let Err(e) = Self::discard(decode, pad, pad_config.realtime) {
+                tracing::error!("{e}");
+            }
+        });
+
+        Ok(Self {
+            name,
+            config,
+            decode,
+            video_convert,
+            clock_sync,
+            video_queue,
+            video_sink: seek_sink,
+        })
+    }
+
+    /// A seek to `start`. Seeks go to the decoded pad, a seek on the bin
+    /// would only be passed on to sink elements and uridecodebin has none.
+    fn seek_event(start: gst::ClockTime, flags: gst::SeekFlags) -> gst::Event {
+        gst::event::Seek::new(
+            1.0,
+            flags,
+            gst::SeekType::Set,
+            start,
+            gst::SeekType::None,
+            gst::ClockTime::NONE,
+        )
+    }
+
+    /// Holds the video back until the first seek is done, so nothing from
+    /// before `start_ms` reaches the encoder. The seek has to come from
+    /// another thread than the blocked one.
+    fn seek_before_start(pad: &gst::Pad, config: &Config) {
+        let start = config.start();
+        let flags = match config.looping {
+            true => gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE | gst::SeekFlags::SEGMENT,
+            false => gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+        };
+        let block = Arc::new(Mutex::new(None));
+        let seeking = AtomicBool::new(false);
+
+        let probe_block = block.clone();
+        let mut guard = block.lock().unwrap_or_else(|e| e.into_inner());
+        *guard = pad.add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, move |pad, _| {
+            if seeking.swap(true, Ordering::SeqCst) {
+                return gst::PadProbeReturn::Ok;
+            }
+            let pad = pad.clone();
+            let block = probe_block.clone();
+            std::thread::spawn(move || {
+                tracing::info!("Seeking to {start}");
+                if !pad.send_event(Self::seek_event(start, flags)) {
+                    tracing::error!("Failed to seek to {start}, the media is not seekable");
+                }
+                if let Some(block) = block.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                    pad.remove_probe(block);
+                }
+            });
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Links a stream that isn't recorded to a fakesink, an unlinked pad
+    /// would stop the decoder.
+    fn discard(decode: &gst::Element, pad: &gst::Pad, realtime: bool) -> anyhow::Result<()> {
+        let bin = decode
+            .parent()
+            .and_then(|parent| parent.downcast::<gst::Bin>().ok())
+            .ok_or_else(|| RecorderError::ElementError("Decoder is not in a bin".to_string()))?;
+        let sink = gst_create_element("fakesink", &format!("{}_{}", decode.name(), pad.name()))?;
+        sink.set_property("sync", realtime);
+        sink.set_property("async", false);
+
+        bin.add(&sink).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to add fakesink for {}: {e}", pad.name()))
+        })?;
+        sink.sync_state_with_parent()
+            .map_err(|e| RecorderError::ElementError(format!("Failed to start fakesink: {e}")))?;
+        let sink_pad = sink
+            .static_pad("sink")
+            .ok_or_else(|| RecorderError::ElementError("Fakesink has no sink pad".to_string()))?;
+        pad.link(&sink_pad).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to discard {}: {e:?}", pad.name()))
+        })?;
+        tracing::info!("Discarding stream {}", pad.name());
+        Ok(())
+    }
+
+    /// Starts the media over after a segment seek reached its end, without
+    /// flushing, so the running time keeps going.
+    pub fn segment_done(&self) -> anyhow::Result<()> {
+        if !self.config.looping {
+            return Ok(());
+        }
+        let start = self.config.start();
+        tracing::info!("Looping {} from {start}", self.name);
+        // Pushed from the sink pad to its peer, the decoded pad.
+        let seek = Self::seek_event(start, gst::SeekFlags::SEGMENT | gst::SeekFlags::ACCURATE);
+        if !self.video_sink.push_event(seek) {
+            return Err(RecorderError::InputError(format!(
+                "Failed to loop {} from {start}",
+                self.name
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    pub fn _name(&self) -> &str {
+        &self.name
+    }
+
+    fn elements(&self) -> [&gst::Element; 4] {
+        [
+            &self.decode,
+            &self.video_convert,
+            &self.clock_sync,
+            &self.video_queue,
+        ]
+    }
+}
+
+impl Drop for Uri {
+    fn drop(&mut self) {
+        tracing::info!("Dropping Uri input {}", &self.name);
+    }
+}
+
+impl Pipeline for Uri {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Linking Uri input {}", &self.name);
+        pipeline.add_many(&self.elements()).map_err(|_| {
+            RecorderError::ElementError(format!("Error adding {} elements to pipeline", self.name))
+        })?;
+
+        // The decoder is linked once it knows its streams.
+        gst::Element::link_many(&[&self.video_convert, &self.clock_sync, &self.video_queue])
+            .map_err(|_| {
+                RecorderError::ElementError(format!("Error linking {} video elements", self.name))
+            })?;
+
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        pipeline.remove_many(&self.elements()).map_err(|_| {
+            RecorderError::ElementError(format!("Error unlinking {} elements", self.name))
+        })?;
+
+        Ok(())
+    }
+}
+
+impl PipelineSrc for Uri {
+    fn source(&self) -> gst::Element {
+        self.video_queue.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_uri_accepts_a_scheme_and_separator() {
+        assert!(is_uri("file:///tmp/in.mp4"));
+        assert!(is_uri("rtsp://camera.local/stream"));
+        assert!(is_uri("svn+ssh://host/path"));
+    }
+
+    #[test]
+    fn is_uri_rejects_paths_and_invalid_schemes() {
+        assert!(!is_uri("/tmp/in.mp4"));
+        assert!(!is_uri("in.mp4"));
+        assert!(!is_uri("file:/tmp/in.mp4"));
+        assert!(!is_uri("://host/path"));
+        assert!(!is_uri("1http://host/path"));
+        assert!(!is_uri("/tmp/a://b.mp4"));
+    }
+}
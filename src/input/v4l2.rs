@@ -1,10 +1,14 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
-use crate::recorder::errors::RecorderError;
+mod controls;
+
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::{Pipeline, PipelineSrc};
-use crate::util::gst_create_element;
+use crate::util::{gst_create_element, gst_describe_caps, gst_set_property_from_str};
+pub use controls::Control;
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Serialize, Clone, Default)]
@@ -15,20 +19,117 @@ pub struct Stats {
     video_queue_overrun_count: u64,
 }
 
+/// Nicks of the v4l2src io-mode property.
+pub const IO_MODES: [&str; 6] = ["auto", "rw", "mmap", "userptr", "dmabuf", "dmabuf-import"];
+
+/// What the camera sends. MJPEG is decoded with jpegdec.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Raw,
+    Mjpeg,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct Config {
     pub device: String,
+    /* Requested caps. Fields that are None are left to the negotiation with
+    the device. */
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub framerate: Option<u32>, // Frames per second
+    pub format: Format,
+    pub io_mode: String,      // One of IO_MODES
+    pub norm: Option<String>, // Analog video standard, e.g. "PAL" or "NTSC"
+    /* Controls set when the device is opened, e.g. {"gain": 10}. Names as
+    listed by GET /inputs/{name}/controls or `v4l2-ctl --list-ctrls`. */
+    pub controls: BTreeMap<String, i32>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             device: "/dev/video0".to_string(),
+            width: None,
+            height: None,
+            framerate: None,
+            format: Format::Raw,
+            io_mode: "auto".to_string(),
+            norm: None,
+            controls: BTreeMap::new(),
         }
     }
 }
 
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.device.is_empty() {
+            errors.push(FieldError::new(
+                format!("{field}.device"),
+                "must not be empty",
+            ));
+        }
+        for (name, value) in [
+            ("width", self.width),
+            ("height", self.height),
+            ("framerate", self.framerate),
+        ] {
+            match value {
+                Some(0) => errors.push(FieldError::new(
+                    format!("{field}.{name}"),
+                    "must be greater than 0",
+                )),
+                Some(value) if value > i32::MAX as u32 => errors.push(FieldError::new(
+                    format!("{field}.{name}"),
+                    format!("must be at most {}", i32::MAX),
+                )),
+                _ => {}
+            }
+        }
+        if !IO_MODES.contains(&self.io_mode.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.io_mode"),
+                format!(
+                    "unknown io mode {}, expected one of {}",
+                    self.io_mode,
+                    IO_MODES.join(", ")
+                ),
+            ));
+        }
+        if matches!(&self.norm, Some(norm) if norm.is_empty()) {
+            errors.push(FieldError::new(
+                format!("{field}.norm"),
+                "must not be empty",
+            ));
+        }
+        if self.controls.keys().any(|name| name.is_empty()) {
+            errors.push(FieldError::new(
+                format!("{field}.controls"),
+                "control names must not be empty",
+            ));
+        }
+    }
+
+    /// Caps the device has to deliver.
+    fn caps(&self) -> gst::Caps {
+        let media_type = match self.format {
+            Format::Raw => "video/x-raw",
+            Format::Mjpeg => "image/jpeg",
+        };
+        let mut caps = gst::Caps::builder(media_type);
+        if let Some(width) = self.width {
+            caps = caps.field("width", width as i32);
+        }
+        if let Some(height) = self.height {
+            caps = caps.field("height", height as i32);
+        }
+        if let Some(framerate) = self.framerate {
+            caps = caps.field("framerate", gst::Fraction::new(framerate as i32, 1));
+        }
+        caps.build()
+    }
+}
+
 #[derive(Debug)]
 pub struct V4l2 {
     pub name: String,
@@ -36,17 +137,44 @@ pub struct V4l2 {
     stats: Arc<Mutex<Stats>>,
 
     video: gst::Element,
+    caps_filter: gst::Element,
+    /// Only for MJPEG.
+    jpeg_dec: Option<gst::Element>,
     video_queue: gst::Element,
 }
 
 impl V4l2 {
     pub fn new(name: String, config: Config) -> anyhow::Result<Self> {
-        tracing::info!("Creating Test input {}", &name);
+        tracing::info!("Creating V4l2 input {}", &name);
 
         let stats = Arc::new(Mutex::new(Stats::default()));
 
         let video = gst_create_element("v4l2src", &format!("input_{}_v4l2src", &name))?;
         video.set_property("device", &config.device);
+        gst_set_property_from_str(&video, "io-mode", &config.io_mode)?;
+        if let Some(norm) = &config.norm {
+            gst_set_property_from_str(&video, "norm", norm)?;
+        }
+
+        let caps = config.caps();
+        Self::check_caps(&video, &config.device, &caps)?;
+        if !config.controls.is_empty() {
+            controls::check(&config.device, &config.controls)?;
+            let mut extra_controls = gst::Structure::new_empty("controls");
+            for (name, value) in &config.controls {
+                extra_controls.set(name.as_str(), *value);
+            }
+            video.set_property("extra-controls", &extra_controls);
+        }
+        let caps_filter = gst_create_element("capsfilter", &format!("input_{}_caps", &name))?;
+        caps_filter.set_property("caps", &caps);
+        let jpeg_dec = match config.format {
+            Format::Raw => None,
+            Format::Mjpeg => Some(gst_create_element(
+                "jpegdec",
+                &format!("input_{}_jpegdec", &name),
+            )?),
+        };
 
         let video_queue = gst_create_element("queue", &format!("input_{}_video_queue", &name))?;
         let stat = stats.clone();
@@ -62,10 +190,62 @@ impl V4l2 {
             stats,
 
             video,
+            caps_filter,
+            jpeg_dec,
             video_queue,
         })
     }
 
+    /// Opens the device to check that it can deliver `caps`, so a mismatch
+    /// is reported with the caps it does support instead of failing the
+    /// negotiation later.
+    fn check_caps(video: &gst::Element, device: &str, caps: &gst::Caps) -> anyhow::Result<()> {
+        video.set_state(gst::State::Ready).map_err(|_| {
+            RecorderError::InputError(format!("Failed to open V4L2 device {device}"))
+        })?;
+        let supported = video.static_pad("src").map(|pad| pad.query_caps(None));
+        if let Err(e) = video.set_state(gst::State::Null) {
+            tracing::warn!("Failed to close V4L2 device {device}: {e}");
+        }
+
+        let supported = supported.ok_or_else(|| {
+            RecorderError::InputError(format!("V4L2 device {device} has no src pad"))
+        })?;
+        if !supported.can_intersect(caps) {
+            return Err(RecorderError::ConfigError(format!(
+                "V4L2 device {device} does not support {caps}, it supports: {}",
+                gst_describe_caps(&supported).join(", ")
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The controls of the device with their current values.
+    pub fn controls(&self) -> Result<Vec<Control>, RecorderError> {
+        controls::query(&self.config.device)
+    }
+
+    /// Sets controls of the device while it is recording. Returns all
+    /// controls with their new values.
+    pub fn set_controls(
+        &mut self,
+        values: &BTreeMap<String, i32>,
+    ) -> Result<Vec<Control>, RecorderError> {
+        let controls = controls::set(&self.config.device, values)?;
+        self.config.controls.extend(values.clone());
+        Ok(controls)
+    }
+
+    /// The elements from the device to the queue, in order.
+    fn elements(&self) -> Vec<&gst::Element> {
+        [&self.video, &self.caps_filter]
+            .into_iter()
+            .chain(self.jpeg_dec.iter())
+            .chain(std::iter::once(&self.video_queue))
+            .collect()
+    }
+
     pub fn _name(&self) -> &str {
         &self.name
     }
@@ -98,16 +278,11 @@ impl Drop for V4l2 {
 impl Pipeline for V4l2 {
     fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
         tracing::info!("Linking V4l2 input {}", &self.name);
-        pipeline
-            .add_many(&[&self.video, &self.video_queue])
-            .map_err(|_| {
-                RecorderError::ElementError(format!(
-                    "Error adding {} elements to pipeline",
-                    self.name
-                ))
-            })?;
-
-        gst::Element::link_many(&[&self.video, &self.video_queue]).map_err(|_| {
+        pipeline.add_many(&self.elements()).map_err(|_| {
+            RecorderError::ElementError(format!("Error adding {} elements to pipeline", self.name))
+        })?;
+
+        gst::Element::link_many(&self.elements()).map_err(|_| {
             RecorderError::ElementError(format!("Error linking {} video elements", self.name))
         })?;
 
@@ -115,11 +290,9 @@ impl Pipeline for V4l2 {
     }
 
     fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
-        pipeline
-            .remove_many(&[&self.video, &self.video_queue])
-            .map_err(|_| {
-                RecorderError::ElementError(format!("Error unlinking {} elements", self.name))
-            })?;
+        pipeline.remove_many(&self.elements()).map_err(|_| {
+            RecorderError::ElementError(format!("Error unlinking {} elements", self.name))
+        })?;
 
         Ok(())
     }
@@ -0,0 +1,297 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::recorder::errors::RecorderError;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+
+// From linux/videodev2.h
+const VIDIOC_QUERYCTRL: u64 = 0xC044_5624;
+const VIDIOC_QUERYMENU: u64 = 0xC02C_5625;
+const VIDIOC_G_CTRL: u64 = 0xC008_561B;
+const VIDIOC_S_CTRL: u64 = 0xC008_561C;
+
+const V4L2_CTRL_FLAG_NEXT_CTRL: u32 = 0x8000_0000;
+const V4L2_CTRL_FLAG_DISABLED: u32 = 0x0001;
+const V4L2_CTRL_FLAG_READ_ONLY: u32 = 0x0004;
+const V4L2_CTRL_FLAG_INACTIVE: u32 = 0x0010;
+const V4L2_CTRL_FLAG_WRITE_ONLY: u32 = 0x0040;
+
+const V4L2_CTRL_TYPE_INTEGER: u32 = 1;
+const V4L2_CTRL_TYPE_BOOLEAN: u32 = 2;
+const V4L2_CTRL_TYPE_MENU: u32 = 3;
+const V4L2_CTRL_TYPE_BUTTON: u32 = 4;
+const V4L2_CTRL_TYPE_BITMASK: u32 = 8;
+const V4L2_CTRL_TYPE_INTEGER_MENU: u32 = 9;
+
+#[repr(C)]
+#[derive(Default)]
+struct V4l2QueryCtrl {
+    id: u32,
+    type_: u32,
+    name: [u8; 32],
+    minimum: i32,
+    maximum: i32,
+    step: i32,
+    default_value: i32,
+    flags: u32,
+    reserved: [u32; 2],
+}
+
+#[repr(C, packed)]
+#[derive(Default)]
+struct V4l2QueryMenu {
+    id: u32,
+    index: u32,
+    // Union of the item name and, for integer menus, its 64 bit value.
+    name: [u8; 32],
+    reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct V4l2Control {
+    id: u32,
+    value: i32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MenuItem {
+    pub index: i32,
+    pub name: String,
+}
+
+/// A control of a V4L2 device, e.g. exposure or focus. Controls belong to
+/// the device, so they can be read and set on a separate file descriptor
+/// while v4l2src is streaming.
+#[derive(Debug, Serialize, Clone)]
+pub struct Control {
+    /// Name used in `Config::controls`, e.g. "exposure_time_absolute". The
+    /// same as v4l2-ctl and the extra-controls of v4l2src use.
+    pub name: String,
+    /// Name reported by the driver, e.g. "Exposure Time, Absolute".
+    pub label: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// `None` for controls that can't be read, e.g. buttons.
+    pub value: Option<i32>,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default: i32,
+    pub read_only: bool,
+    /// Ignored by the device right now, e.g. the exposure time while auto
+    /// exposure is on.
+    pub inactive: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub menu: Vec<MenuItem>,
+    #[serde(skip)]
+    id: u32,
+}
+
+/// The controls of `device` with their current values.
+pub fn query(device: &str) -> Result<Vec<Control>, RecorderError> {
+    let file = open(device)?;
+    let mut controls = vec![];
+    let mut query = V4l2QueryCtrl {
+        id: V4L2_CTRL_FLAG_NEXT_CTRL,
+        ..Default::default()
+    };
+    while ioctl(&file, VIDIOC_QUERYCTRL, &mut query).is_ok() {
+        let id = query.id;
+        query.id |= V4L2_CTRL_FLAG_NEXT_CTRL;
+
+        let kind = match query.type_ {
+            V4L2_CTRL_TYPE_INTEGER => "integer",
+            V4L2_CTRL_TYPE_BOOLEAN => "boolean",
+            V4L2_CTRL_TYPE_MENU => "menu",
+            V4L2_CTRL_TYPE_BUTTON => "button",
+            V4L2_CTRL_TYPE_BITMASK => "bitmask",
+            V4L2_CTRL_TYPE_INTEGER_MENU => "integer_menu",
+            // Control classes, and types that don't fit VIDIOC_G_CTRL.
+            _ => continue,
+        };
+        if query.flags & V4L2_CTRL_FLAG_DISABLED != 0 {
+            continue;
+        }
+
+        let label = c_string(&query.name);
+        let readable =
+            query.type_ != V4L2_CTRL_TYPE_BUTTON && query.flags & V4L2_CTRL_FLAG_WRITE_ONLY == 0;
+        let value = match readable {
+            true => get(&file, id, &label)
+                .map_err(|e| tracing::warn!("{device}: {e}"))
+                .ok(),
+            false => None,
+        };
+        let menu = match query.type_ {
+            V4L2_CTRL_TYPE_MENU | V4L2_CTRL_TYPE_INTEGER_MENU => menu(&file, &query, id),
+            _ => vec![],
+        };
+        controls.push(Control {
+            name: normalise_name(&label),
+            label,
+            kind: kind.to_string(),
+            value,
+            minimum: query.minimum,
+            maximum: query.maximum,
+            step: query.step,
+            default: query.default_value,
+            read_only: query.flags & V4L2_CTRL_FLAG_READ_ONLY != 0,
+            inactive: query.flags & V4L2_CTRL_FLAG_INACTIVE != 0,
+            menu,
+            id,
+        });
+    }
+    Ok(controls)
+}
+
+/// Checks that every control in `values` exists on `device`, can be set and
+/// that the value is in range.
+pub fn check(device: &str, values: &BTreeMap<String, i32>) -> Result<Vec<Control>, RecorderError> {
+    let controls = query(device)?;
+    for (name, value) in values {
+        let control = controls.iter().find(|c| &c.name == name).ok_or_else(|| {
+            RecorderError::ConfigError(format!(
+                "V4L2 device {device} has no control {name}, it has: {}",
+                controls
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })?;
+        if control.read_only {
+            return Err(RecorderError::ConfigError(format!(
+                "Control {name} of V4L2 device {device} is read only"
+            )));
+        }
+        if control.kind != "bitmask" && (*value < control.minimum || *value > control.maximum) {
+            return Err(RecorderError::ConfigError(format!(
+                "Control {name} of V4L2 device {device} must be between {} and {}",
+                control.minimum, control.maximum
+            )));
+        }
+    }
+    Ok(controls)
+}
+
+/// Sets the controls in `values` on `device` and returns all controls with
+/// their new values. Stops at the first control that fails.
+pub fn set(device: &str, values: &BTreeMap<String, i32>) -> Result<Vec<Control>, RecorderError> {
+    let controls = check(device, values)?;
+    let file = open(device)?;
+    for (name, value) in values {
+        if let Some(control) = controls.iter().find(|c| &c.name == name) {
+            let mut control = V4l2Control {
+                id: control.id,
+                value: *value,
+            };
+            ioctl(&file, VIDIOC_S_CTRL, &mut control).map_err(|e| {
+                RecorderError::InputError(format!(
+                    "Failed to set control {name} of V4L2 device {device} to {value}: {e}"
+                ))
+            })?;
+            tracing::info!("Set control {name} of {device} to {value}");
+        }
+    }
+    query(device)
+}
+
+fn get(file: &File, id: u32, label: &str) -> Result<i32, RecorderError> {
+    let mut control = V4l2Control { id, value: 0 };
+    ioctl(file, VIDIOC_G_CTRL, &mut control)
+        .map_err(|e| RecorderError::InputError(format!("Failed to read control {label}: {e}")))?;
+    Ok(control.value)
+}
+
+/// The items of a menu control. Drivers leave out indexes they don't
+/// support.
+fn menu(file: &File, query: &V4l2QueryCtrl, id: u32) -> Vec<MenuItem> {
+    (query.minimum..=query.maximum)
+        .filter_map(|index| {
+            let mut item = V4l2QueryMenu {
+                id,
+                index: index as u32,
+                ..Default::default()
+            };
+            ioctl(file, VIDIOC_QUERYMENU, &mut item).ok()?;
+            let name = match query.type_ {
+                V4L2_CTRL_TYPE_INTEGER_MENU => {
+                    let mut value = [0u8; 8];
+                    value.copy_from_slice(&item.name[..8]);
+                    i64::from_ne_bytes(value).to_string()
+                }
+                _ => c_string(&item.name),
+            };
+            Some(MenuItem { index, name })
+        })
+        .collect()
+}
+
+fn open(device: &str) -> Result<File, RecorderError> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(device)
+        .map_err(|e| RecorderError::InputError(format!("Failed to open V4L2 device {device}: {e}")))
+}
+
+fn ioctl<T>(file: &File, request: u64, arg: &mut T) -> std::io::Result<()> {
+    // SAFETY: `arg` is the struct the kernel expects for `request`.
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg as *mut T) };
+    match result {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+/// Lower case with runs of other characters replaced by one underscore,
+/// like v4l2src does for extra-controls. "Exposure Time, Absolute" becomes
+/// "exposure_time_absolute".
+fn normalise_name(label: &str) -> String {
+    let mut name = String::new();
+    let mut separate = false;
+    for c in label.chars() {
+        if c.is_ascii_alphanumeric() {
+            if separate && !name.is_empty() {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+            separate = false;
+        } else {
+            separate = true;
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalise_name_lowercases_and_joins_words() {
+        assert_eq!(
+            normalise_name("Exposure Time, Absolute"),
+            "exposure_time_absolute"
+        );
+        assert_eq!(normalise_name("Brightness"), "brightness");
+        assert_eq!(normalise_name("H264 I-Frame Period"), "h264_i_frame_period");
+    }
+
+    #[test]
+    fn normalise_name_drops_outer_and_repeated_separators() {
+        assert_eq!(
+            normalise_name("  White Balance --  Auto "),
+            "white_balance_auto"
+        );
+        assert_eq!(normalise_name("(Gain)"), "gain");
+        assert_eq!(normalise_name(" - "), "");
+    }
+}
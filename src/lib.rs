@@ -1,3 +1,4 @@
+pub mod config;
 mod encoder;
 mod input;
 mod output;
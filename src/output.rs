@@ -1,7 +1,12 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 mod fakesink;
-mod filesink;
-mod muxer;
+pub mod filesink;
+pub mod hls;
+pub mod muxer;
+pub mod splitfile;
+pub mod tee;
+use crate::encoder::Codec;
+use crate::recorder::errors::FieldError;
 use crate::traits::{Pipeline, PipelineSink};
 
 use anyhow::Result;
@@ -25,10 +30,49 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.name.is_empty() {
+            errors.push(FieldError::new(
+                format!("{field}.name"),
+                "must not be empty",
+            ));
+        }
+        match &self.variant {
+            Variant::FakeSink(_) => {}
+            Variant::FileSink(c) => c.validate(&format!("{field}.variant.FileSink"), errors),
+            Variant::SplitFile(c) => c.validate(&format!("{field}.variant.SplitFile"), errors),
+            Variant::Hls(c) => c.validate(&format!("{field}.variant.Hls"), errors),
+        }
+    }
+
+    /// Whether the output can take video encoded with `codec`.
+    pub fn supports(&self, codec: Codec) -> bool {
+        match &self.variant {
+            Variant::FakeSink(_) => true,
+            Variant::FileSink(c) => c.muxer_config.supports(codec),
+            Variant::SplitFile(c) => c.muxer_config.supports(codec),
+            Variant::Hls(_) => hls::CODECS.contains(&codec),
+        }
+    }
+
+    /// Where the output is written to, if it is written anywhere.
+    pub fn location(&self) -> Option<String> {
+        match &self.variant {
+            Variant::FakeSink(_) => None,
+            Variant::FileSink(c) => Some(c.location.clone()),
+            Variant::SplitFile(c) => Some(c.location.clone()),
+            Variant::Hls(c) => Some(c.playlist_location()),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Variant {
     FakeSink(fakesink::Config),
     FileSink(crate::output::filesink::Config),
+    SplitFile(splitfile::Config),
+    Hls(hls::Config),
 }
 
 impl Default for Variant {
@@ -41,10 +85,14 @@ impl Default for Variant {
 pub enum Output {
     FakeSink(fakesink::FakeSink),
     FileSink(filesink::FileSink),
+    SplitFile(splitfile::SplitFile),
+    Hls(hls::Hls),
 }
 
 impl Output {
-    pub fn new(config: Config) -> anyhow::Result<Self> {
+    /// `codec` is what the encoder produces, the outputs parse it into the
+    /// stream format their muxer needs.
+    pub fn new(config: Config, codec: Codec) -> anyhow::Result<Self> {
         match config.variant {
             Variant::FakeSink(c) => Ok(Output::FakeSink(fakesink::FakeSink::new(
                 config.name.clone(),
@@ -53,7 +101,44 @@ impl Output {
             Variant::FileSink(c) => Ok(Output::FileSink(filesink::FileSink::new(
                 config.name.clone(),
                 c,
+                codec,
+            )?)),
+            Variant::SplitFile(c) => Ok(Output::SplitFile(splitfile::SplitFile::new(
+                config.name.clone(),
+                c,
+                codec,
             )?)),
+            Variant::Hls(c) => Ok(Output::Hls(hls::Hls::new(config.name.clone(), c, codec)?)),
+        }
+    }
+
+    /// Elements of the output, to tell which output a bus message came from.
+    pub fn elements(&self) -> Vec<gst::Element> {
+        match self {
+            Output::FakeSink(sink) => sink.elements(),
+            Output::FileSink(sink) => sink.elements(),
+            Output::SplitFile(sink) => sink.elements(),
+            Output::Hls(sink) => sink.elements(),
+        }
+    }
+
+    /// Called once the output's sink handled EOS and all data has been
+    /// written, when the recording ends or the output is removed.
+    pub fn fsync(&self) -> anyhow::Result<()> {
+        match self {
+            Output::FakeSink(_) => Ok(()),
+            Output::FileSink(sink) => sink.fsync(),
+            // Fragments are synced as they are closed.
+            Output::SplitFile(_) => Ok(()),
+            Output::Hls(_) => Ok(()),
+        }
+    }
+
+    /// Called when splitmuxsink closed the fragment at `location`.
+    pub fn fragment_closed(&self, location: &str) -> anyhow::Result<()> {
+        match self {
+            Output::SplitFile(sink) => sink.fragment_closed(location),
+            _ => Ok(()),
         }
     }
 }
@@ -63,6 +148,8 @@ impl Pipeline for Output {
         match self {
             Output::FakeSink(f) => f.link(pipeline),
             Output::FileSink(fs) => fs.link(pipeline),
+            Output::SplitFile(sf) => sf.link(pipeline),
+            Output::Hls(hls) => hls.link(pipeline),
         }
     }
 
@@ -70,6 +157,8 @@ impl Pipeline for Output {
         match self {
             Output::FakeSink(sink) => sink.unlink(&pipeline),
             Output::FileSink(sink) => sink.unlink(&pipeline),
+            Output::SplitFile(sink) => sink.unlink(pipeline),
+            Output::Hls(sink) => sink.unlink(pipeline),
         }
     }
 }
@@ -79,6 +168,8 @@ impl PipelineSink for Output {
         match self {
             Output::FakeSink(sink) => sink.sink(),
             Output::FileSink(sink) => sink.sink(),
+            Output::SplitFile(sink) => sink.sink(),
+            Output::Hls(sink) => sink.sink(),
         }
     }
 }
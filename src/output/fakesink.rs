@@ -1,6 +1,6 @@
 use crate::recorder::errors::RecorderError;
 use crate::traits::*;
-use crate::util::gst_create_element;
+use crate::util::{gst_create_element, gst_set_null, gst_sync_state_with_parent};
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -30,6 +30,7 @@ impl Default for FakeStats {
 #[derive(Debug)]
 pub struct FakeSink {
     pub name: String,
+    video_queue: gst::Element,
     video: gst::Element,
 }
 
@@ -41,12 +42,21 @@ impl FakeSink {
     pub fn new(name: String, config: Config) -> anyhow::Result<Self> {
         tracing::info!("Creating Fake input {}", &name);
 
+        let video_queue = gst_create_element("queue", &format!("output_{}_video_queue", &name))?;
         let video = gst_create_element("fakesink", &format!("output_{}_fakesink_video", &name))
             .map_err(|_| {
                 RecorderError::ElementError(format!("Error creating {} video fakesink", &name))
             })?;
 
-        Ok(FakeSink { name, video })
+        Ok(FakeSink {
+            name,
+            video_queue,
+            video,
+        })
+    }
+
+    pub fn elements(&self) -> Vec<gst::Element> {
+        vec![self.video_queue.clone(), self.video.clone()]
     }
 
     pub fn _get_stats(&self) -> FakeStats {
@@ -63,29 +73,39 @@ impl Drop for FakeSink {
 impl Pipeline for FakeSink {
     fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
         tracing::info!("Linking Fake input {}", &self.name);
-        pipeline.add_many(&[&self.video]).map_err(|_| {
-            RecorderError::ElementError(format!(
-                "Error adding {} audio and video fakesink elements",
-                self.name
-            ))
+        pipeline
+            .add_many(&[&self.video_queue, &self.video])
+            .map_err(|_| {
+                RecorderError::ElementError(format!(
+                    "Error adding {} audio and video fakesink elements",
+                    self.name
+                ))
+            })?;
+        self.video_queue.link(&self.video).map_err(|_| {
+            RecorderError::ElementError(format!("Error linking {} fakesink elements", self.name))
         })?;
 
+        gst_sync_state_with_parent(&self.elements())?;
+
         Ok(())
     }
 
     fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
-        pipeline.remove_many(&[&self.video]).map_err(|_| {
-            RecorderError::ElementError(format!(
-                "Error removing {} audio and video fakesink elements",
-                self.name
-            ))
-        })?;
+        pipeline
+            .remove_many(&[&self.video_queue, &self.video])
+            .map_err(|_| {
+                RecorderError::ElementError(format!(
+                    "Error removing {} audio and video fakesink elements",
+                    self.name
+                ))
+            })?;
+        gst_set_null(&self.elements());
         Ok(())
     }
 }
 
 impl PipelineSink for FakeSink {
     fn sink(&self) -> gst::Element {
-        self.video.clone()
+        self.video_queue.clone()
     }
 }
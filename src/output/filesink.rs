@@ -1,12 +1,14 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 // use super::media::encoder_config::EncoderConfig;
 // use super::media::muxer::Muxer;
-use crate::output::muxer::{
-    Config as MuxerConfig, FlvConfig, MatroskaConfig, Mp4Config, MpegTsConfig, WebmConfig,
-};
-use crate::recorder::errors::RecorderError;
+use crate::encoder::Codec;
+use crate::output::muxer::Config as MuxerConfig;
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::*;
-use crate::util::gst_create_element;
+use crate::util::{
+    check_writable, fsync_file, gst_create_element, gst_create_muxer, gst_create_parser,
+    gst_set_null, gst_sync_state_with_parent, set_filesink_props,
+};
 
 use gstreamer as gst;
 use gstreamer::prelude::*;
@@ -20,7 +22,7 @@ pub struct Config {
     pub append: bool,
     pub async_to_pause: bool,
     pub blocksize: u32,
-    pub buffer_mode: String,
+    pub buffer_mode: BufferMode,
     pub buffer_size: u32,
     pub max_bitrate: u64,
     pub max_lateness: i64,
@@ -31,7 +33,10 @@ pub struct Config {
     pub render_delay: u64,
     pub sync: bool,
     pub throttle_time: u64,
-    pub ts_offset: u64,
+    pub ts_offset: i64,
+    /// `fsync` the file once it is complete, when the recording stops or the
+    /// output is removed, so it survives a power cut right after.
+    pub fsync: bool,
 }
 
 impl Default for Config {
@@ -41,7 +46,7 @@ impl Default for Config {
             append: false,
             async_to_pause: true,
             blocksize: 4096,
-            buffer_mode: "default".to_string(),
+            buffer_mode: BufferMode::Default,
             buffer_size: 65535,
             location: "/tmp/output.mkv".to_string(),
             max_bitrate: 0,
@@ -54,6 +59,71 @@ impl Default for Config {
             sync: false,
             throttle_time: 0,
             ts_offset: 0,
+            fsync: false,
+        }
+    }
+}
+
+/// stdio buffering of the file, `buffer_size` sets the buffer size for
+/// `Full` and `Line`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BufferMode {
+    Default,
+    Full,
+    Line,
+    Unbuffered,
+}
+
+impl BufferMode {
+    /// Nick of the value in `GstFileSinkBufferMode`.
+    pub fn nick(&self) -> &'static str {
+        match self {
+            BufferMode::Default => "default",
+            BufferMode::Full => "full",
+            BufferMode::Line => "line",
+            BufferMode::Unbuffered => "unbuffered",
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.location.is_empty() {
+            errors.push(FieldError::new(
+                format!("{field}.location"),
+                "must not be empty",
+            ));
+        } else if let Err(message) = check_writable(std::path::Path::new(&self.location)) {
+            errors.push(FieldError::new(format!("{field}.location"), message));
+        }
+        if self.blocksize == 0 {
+            errors.push(FieldError::new(
+                format!("{field}.blocksize"),
+                "must be greater than 0",
+            ));
+        }
+        if matches!(self.buffer_mode, BufferMode::Full | BufferMode::Line) && self.buffer_size == 0
+        {
+            errors.push(FieldError::new(
+                format!("{field}.buffer_size"),
+                format!(
+                    "must be greater than 0 with buffer_mode {}",
+                    self.buffer_mode.nick()
+                ),
+            ));
+        }
+        if self.max_lateness < -1 {
+            errors.push(FieldError::new(
+                format!("{field}.max_lateness"),
+                "must be -1 (unlimited) or greater",
+            ));
+        }
+        if self.max_transient_error_timeout < 0 {
+            errors.push(FieldError::new(
+                format!("{field}.max_transient_error_timeout"),
+                "must not be negative",
+            ));
         }
     }
 }
@@ -64,12 +134,13 @@ pub struct FileSink {
     config: Config,
 
     video_queue: gst::Element,
+    parser: Option<gst::Element>,
     muxer: gst::Element,
     filesink: gst::Element,
 }
 
 impl FileSink {
-    pub fn new(name: String, config: Config) -> anyhow::Result<Self> {
+    pub fn new(name: String, config: Config, codec: Codec) -> anyhow::Result<Self> {
         tracing::info!("Creating FileSink output {}", &name);
 
         let video_queue = gst_create_element("queue", &format!("output_{}_video_queue", &name))?;
@@ -78,42 +149,43 @@ impl FileSink {
             None
         });
 
-        let muxer = match config.muxer_config {
-            MuxerConfig::Mpeg4(_) => gst_create_element(
-                Mp4Config::name(),
-                &format!("{}_output_muxer", Mp4Config::name()),
-            )?,
-            MuxerConfig::Flv(_) => gst_create_element(
-                FlvConfig::name(),
-                &format!("{}_output_muxer", FlvConfig::name()),
-            )?,
-            MuxerConfig::MpegTs(_) => gst_create_element(
-                MpegTsConfig::name(),
-                &format!("{}_output_muxer", MpegTsConfig::name()),
-            )?,
-            MuxerConfig::Matroska(_) => gst_create_element(
-                MatroskaConfig::name(),
-                &format!("{}_output_muxer", MatroskaConfig::name()),
-            )?,
-            MuxerConfig::Webm(_) => gst_create_element(
-                WebmConfig::name(),
-                &format!("{}_output_muxer", &WebmConfig::name()),
-            )?,
-        };
-
-        let filesink = gst_create_element("filesink", &format!("{}_output_filesink", &name))?;
-        filesink.set_property("location", &config.location);
+        let parser = gst_create_parser(codec, &format!("output_{}_parser", &name))?;
+        let muxer = gst_create_muxer(&config.muxer_config, &format!("output_{}_muxer", &name))?;
+
+        let filesink = gst_create_element("filesink", &format!("output_{}_filesink", &name))?;
+        set_filesink_props(&filesink, &config)?;
 
         Ok(FileSink {
             name,
             config,
             video_queue,
+            parser,
             muxer,
             filesink,
         })
     }
 }
 
+impl FileSink {
+    /// The elements in the order they are linked.
+    pub fn elements(&self) -> Vec<gst::Element> {
+        std::iter::once(self.video_queue.clone())
+            .chain(self.parser.clone())
+            .chain([self.muxer.clone(), self.filesink.clone()])
+            .collect()
+    }
+
+    /// Flushes the finished file to storage if `fsync` is enabled. Must only
+    /// be called after the filesink handled EOS.
+    pub fn fsync(&self) -> anyhow::Result<()> {
+        if !self.config.fsync {
+            return Ok(());
+        }
+        tracing::info!("Syncing {} to storage", self.config.location);
+        fsync_file(std::path::Path::new(&self.config.location))
+    }
+}
+
 impl Drop for FileSink {
     fn drop(&mut self) {
         tracing::info!("Dropping FileSink {}", self.name);
@@ -123,37 +195,39 @@ impl Drop for FileSink {
 impl Pipeline for FileSink {
     fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
         tracing::info!("Linking {} output elements", self.name);
-        pipeline
-            .add_many(&[&self.video_queue, &self.muxer, &self.filesink])
-            .map_err(|e| {
-                RecorderError::ElementError(format!(
-                    "Failed to add elements to pipeline: {}",
-                    e.to_string()
-                ))
-            })?;
-
-        gst::Element::link_many(&[&self.video_queue, &self.muxer, &self.filesink]).map_err(
-            |e| {
-                RecorderError::ElementError(format!(
-                    "Failed to link elements in pipeline: {}",
-                    e.to_string()
-                ))
-            },
-        )?;
+        let elements = self.elements();
+        let elements = elements.iter().collect::<Vec<_>>();
+        pipeline.add_many(&elements).map_err(|e| {
+            RecorderError::ElementError(format!(
+                "Failed to add elements to pipeline: {}",
+                e.to_string()
+            ))
+        })?;
+
+        gst::Element::link_many(&elements).map_err(|e| {
+            RecorderError::ElementError(format!(
+                "Failed to link elements in pipeline: {}",
+                e.to_string()
+            ))
+        })?;
+
+        gst_sync_state_with_parent(&self.elements())?;
 
         Ok(())
     }
 
     fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
         tracing::info!("Unlinking {} output elements", self.name);
+        let elements = self.elements();
         pipeline
-            .remove_many(&[&self.video_queue, &self.muxer, &self.filesink])
+            .remove_many(&elements.iter().collect::<Vec<_>>())
             .map_err(|e| {
                 RecorderError::ElementError(format!(
                     "Failed to remove elements from pipeline: {}",
                     e.to_string()
                 ))
             })?;
+        gst_set_null(&self.elements());
 
         Ok(())
     }
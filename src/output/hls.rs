@@ -0,0 +1,226 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::encoder::Codec;
+use crate::recorder::errors::{FieldError, RecorderError};
+use crate::traits::*;
+use crate::util::{
+    check_dir_writable, gst_create_element, gst_create_parser, gst_set_null, gst_set_property,
+    gst_sync_state_with_parent,
+};
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// HLS sink elements in order of preference for `element: "auto"`.
+pub const ELEMENTS: [&str; 2] = ["hlssink2", "hlssink3"];
+
+/// Codecs the MPEG-TS segments can carry.
+pub const CODECS: [Codec; 2] = [Codec::H264, Codec::H265];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// One of `ELEMENTS`, or "auto" for the first one installed.
+    pub element: String,
+    /// Directory the playlist and the segments are written to.
+    pub directory: String,
+    pub playlist_name: String,
+    /// Segment duration in seconds.
+    pub target_duration: u32,
+    /// Number of segments in the playlist. 0 lists all segments.
+    pub playlist_length: u32,
+    /// Keep segments that dropped out of the playlist on disk.
+    pub keep_segments: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            element: "auto".to_string(),
+            directory: "/tmp/hls".to_string(),
+            playlist_name: "playlist.m3u8".to_string(),
+            target_duration: 6,
+            playlist_length: 5,
+            keep_segments: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.element != "auto" && !ELEMENTS.contains(&self.element.as_str()) {
+            errors.push(FieldError::new(
+                format!("{field}.element"),
+                format!(
+                    "unknown HLS sink {}, expected one of auto, {}",
+                    self.element,
+                    ELEMENTS.join(", ")
+                ),
+            ));
+        }
+        if let Err(message) = check_dir_writable(Path::new(&self.directory)) {
+            errors.push(FieldError::new(format!("{field}.directory"), message));
+        }
+        if self.playlist_name.is_empty() {
+            errors.push(FieldError::new(
+                format!("{field}.playlist_name"),
+                "must not be empty",
+            ));
+        }
+        if self.target_duration == 0 {
+            errors.push(FieldError::new(
+                format!("{field}.target_duration"),
+                "must be greater than 0",
+            ));
+        }
+    }
+
+    /// Name of the sink element that will be created for this config.
+    pub fn element_name(&self) -> Result<&str, RecorderError> {
+        if self.element != "auto" {
+            return match ELEMENTS.iter().find(|e| **e == self.element) {
+                Some(element) => Ok(element),
+                None => Err(RecorderError::ConfigError(format!(
+                    "Unknown HLS sink {}, expected one of auto, {}",
+                    self.element,
+                    ELEMENTS.join(", ")
+                ))),
+            };
+        }
+
+        match ELEMENTS
+            .iter()
+            .find(|e| gst::ElementFactory::find(e).is_some())
+        {
+            Some(element) => Ok(element),
+            None => Err(RecorderError::OutputError(format!(
+                "HLS needs one of {}",
+                ELEMENTS.join(", ")
+            ))),
+        }
+    }
+
+    pub fn playlist_location(&self) -> String {
+        Path::new(&self.directory)
+            .join(&self.playlist_name)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn segment_location(&self) -> String {
+        Path::new(&self.directory)
+            .join("segment%05d.ts")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Segments kept on disk. Old segments stay around a bit longer than they
+    /// are listed, so players that just loaded the playlist can still get
+    /// them.
+    fn max_files(&self) -> u32 {
+        if self.keep_segments || self.playlist_length == 0 {
+            0
+        } else {
+            self.playlist_length * 2
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Hls {
+    name: String,
+    config: Config,
+
+    video_queue: gst::Element,
+    parser: Option<gst::Element>,
+    hlssink: gst::Element,
+}
+
+impl Hls {
+    pub fn new(name: String, config: Config, codec: Codec) -> anyhow::Result<Self> {
+        tracing::info!("Creating Hls output {}", &name);
+
+        let video_queue = gst_create_element("queue", &format!("output_{}_video_queue", &name))?;
+        video_queue.connect("overrun", false, move |_| {
+            tracing::warn!("Hls Video queue overrun");
+            None
+        });
+
+        let parser = gst_create_parser(codec, &format!("output_{}_parser", &name))?;
+
+        let element = config.element_name()?;
+        let hlssink = gst_create_element(element, &format!("output_{}_hlssink", &name))?;
+        gst_set_property(&hlssink, "location", config.segment_location())?;
+        gst_set_property(&hlssink, "playlist-location", config.playlist_location())?;
+        gst_set_property(&hlssink, "target-duration", config.target_duration)?;
+        gst_set_property(&hlssink, "playlist-length", config.playlist_length)?;
+        match element {
+            "hlssink3" => gst_set_property(&hlssink, "max-num-segment-files", config.max_files())?,
+            _ => gst_set_property(&hlssink, "max-files", config.max_files())?,
+        }
+
+        Ok(Hls {
+            name,
+            config,
+            video_queue,
+            parser,
+            hlssink,
+        })
+    }
+}
+
+impl Hls {
+    /// The elements in the order they are linked.
+    pub fn elements(&self) -> Vec<gst::Element> {
+        std::iter::once(self.video_queue.clone())
+            .chain(self.parser.clone())
+            .chain(std::iter::once(self.hlssink.clone()))
+            .collect()
+    }
+}
+
+impl Drop for Hls {
+    fn drop(&mut self) {
+        tracing::info!("Dropping Hls {}", self.name);
+    }
+}
+
+impl Pipeline for Hls {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Linking {} output elements", self.name);
+        let elements = self.elements();
+        let elements = elements.iter().collect::<Vec<_>>();
+        pipeline.add_many(&elements).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to add elements to pipeline: {e}"))
+        })?;
+
+        // Links to the "video" request pad.
+        gst::Element::link_many(&elements).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to link elements in pipeline: {e}"))
+        })?;
+
+        gst_sync_state_with_parent(&self.elements())?;
+
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Unlinking {} output elements", self.name);
+        let elements = self.elements();
+        pipeline
+            .remove_many(&elements.iter().collect::<Vec<_>>())
+            .map_err(|e| {
+                RecorderError::ElementError(format!("Failed to remove elements from pipeline: {e}"))
+            })?;
+        gst_set_null(&self.elements());
+
+        Ok(())
+    }
+}
+
+impl PipelineSink for Hls {
+    fn sink(&self) -> gst::Element {
+        self.video_queue.clone()
+    }
+}
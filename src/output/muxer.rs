@@ -1,3 +1,4 @@
+use crate::encoder::Codec;
 use serde::{Deserialize, Serialize};
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -31,15 +32,85 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Config::Flv(_) => FlvConfig::name(),
+            Config::Mpeg4(_) => Mp4Config::name(),
+            Config::MpegTs(_) => MpegTsConfig::name(),
+            Config::Matroska(_) => MatroskaConfig::name(),
+            Config::Webm(_) => WebmConfig::name(),
+        }
+    }
+
+    /// Codecs the muxer element accepts on its video sink pad.
+    pub fn codecs(&self) -> &'static [Codec] {
+        use Codec::*;
+
+        match self {
+            Config::Flv(_) => &[H264],
+            Config::Mpeg4(_) => &[H264, H265, AV1, VP9],
+            Config::MpegTs(_) => &[H264, H265],
+            Config::Matroska(_) => &[H264, H265, AV1, VP8, VP9],
+            Config::Webm(_) => &[AV1, VP8, VP9],
+        }
+    }
+
+    pub fn supports(&self, codec: Codec) -> bool {
+        self.codecs().contains(&codec)
+    }
+}
+
+/// How an aggregator based muxer picks the running time of its first output
+/// buffer. `Set` uses `start_time`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StartTimeSelection {
+    Zero,
+    First,
+    Set,
+}
+
+impl StartTimeSelection {
+    /// Nick of the value in `GstAggregatorStartTimeSelection`.
+    pub fn nick(&self) -> &'static str {
+        match self {
+            StartTimeSelection::Zero => "zero",
+            StartTimeSelection::First => "first",
+            StartTimeSelection::Set => "set",
+        }
+    }
+}
+
+/// How mp4mux writes a fragmented file, when `fragment_duration` is set.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FragmentMode {
+    DashOrMss,
+    FirstMoovThenFinalise,
+}
+
+impl FragmentMode {
+    /// Nick of the value in `GstQTMuxFragmentMode`.
+    pub fn nick(&self) -> &'static str {
+        match self {
+            FragmentMode::DashOrMss => "dash-or-mss",
+            FragmentMode::FirstMoovThenFinalise => "first-moov-then-finalise",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct Mp4Config {
     pub faststart: bool,
+    /// Temporary file for `faststart`. Empty lets mp4mux pick a unique one,
+    /// concurrent sessions must not share it.
     pub faststart_file: String,
     pub force_chunks: bool,
     pub force_create_timecode_track: bool,
     pub fragment_duration: u32,
-    pub fragment_mode: String,
+    pub fragment_mode: FragmentMode,
     pub interleave_bytes: u64,
     pub interleave_time: u64,
     pub latency: u64,
@@ -54,7 +125,8 @@ pub struct Mp4Config {
     pub reserved_prefill: bool,
     pub start_gap_threshold: u64,
     pub start_time: u64,
-    pub stgart_time_selection: String,
+    #[serde(alias = "stgart_time_selection")]
+    pub start_time_selection: StartTimeSelection,
     pub streamable: bool,
     pub track_timescale: u32,
 }
@@ -63,11 +135,11 @@ impl Default for Mp4Config {
     fn default() -> Self {
         Self {
             faststart: false,
-            faststart_file: "/tmp/faststartfile".to_string(),
+            faststart_file: String::new(),
             force_chunks: false,
             force_create_timecode_track: false,
             fragment_duration: 0,
-            fragment_mode: "dash-or-mss".to_string(),
+            fragment_mode: FragmentMode::DashOrMss,
             interleave_bytes: 0,
             interleave_time: 250000000,
             latency: 0,
@@ -82,7 +154,7 @@ impl Default for Mp4Config {
             reserved_prefill: false,
             start_gap_threshold: 0,
             start_time: 18446744073709551615,
-            stgart_time_selection: "zero".to_string(),
+            start_time_selection: StartTimeSelection::Zero,
             streamable: false,
             track_timescale: 0,
         }
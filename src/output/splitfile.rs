@@ -0,0 +1,284 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::encoder::Codec;
+use crate::output::muxer::Config as MuxerConfig;
+use crate::recorder::errors::{FieldError, RecorderError};
+use crate::traits::*;
+use crate::util::{
+    check_dir_writable, fsync_file, gst_create_element, gst_create_muxer, gst_create_parser,
+    gst_set_null, gst_set_property, gst_sync_state_with_parent,
+};
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub muxer_config: MuxerConfig,
+    /// File name template. `%0Nd` is replaced with the fragment index padded
+    /// to N digits, everything else is expanded like `strftime` when the
+    /// fragment is opened.
+    pub location: String,
+    /// Duration of a fragment in ns. 0 for no limit.
+    pub max_size_time: u64,
+    /// Size of a fragment in bytes. 0 for no limit.
+    pub max_size_bytes: u64,
+    /// Number of fragments kept on disk, older ones are deleted. 0 keeps all.
+    pub max_files: u32,
+    /// Ask the encoder for a keyframe when `max_size_time` is reached, so
+    /// fragments are cut at exactly that duration instead of at the next
+    /// regular keyframe. Has no effect when splitting by size only.
+    pub send_keyframe_requests: bool,
+    /// `fsync` each fragment once it is closed.
+    pub fsync: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            muxer_config: MuxerConfig::default(),
+            location: "/tmp/output-%Y%m%d-%H%M%S-%05d.mkv".to_string(),
+            max_size_time: 600_000_000_000,
+            max_size_bytes: 0,
+            max_files: 0,
+            send_keyframe_requests: true,
+            fsync: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if self.location.is_empty() {
+            errors.push(FieldError::new(
+                format!("{field}.location"),
+                "must not be empty",
+            ));
+        } else {
+            let dir = match Path::new(&self.location).parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            if let Err(message) = check_dir_writable(dir) {
+                errors.push(FieldError::new(format!("{field}.location"), message));
+            }
+        }
+        if self.max_size_time == 0 && self.max_size_bytes == 0 {
+            errors.push(FieldError::new(
+                format!("{field}.max_size_time"),
+                "max_size_time or max_size_bytes must be set",
+            ));
+        }
+    }
+}
+
+/// Expands the location template for fragment `fragment_id`.
+pub fn format_location(template: &str, fragment_id: u32) -> String {
+    // Replace the fragment index first. `%d` on its own is the day of the
+    // month, so only the form with a width is taken as the index.
+    let mut expanded = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            expanded.push_str("%%");
+            continue;
+        }
+
+        let mut width = String::new();
+        while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+            width.push(*d);
+            chars.next();
+        }
+        if !width.is_empty() && chars.peek() == Some(&'d') {
+            chars.next();
+            let zero_padded = width.starts_with('0');
+            let width = width.parse::<usize>().unwrap_or(0);
+            if zero_padded {
+                expanded.push_str(&format!("{fragment_id:0width$}"));
+            } else {
+                expanded.push_str(&format!("{fragment_id:width$}"));
+            }
+        } else {
+            expanded.push('%');
+            expanded.push_str(&width);
+        }
+    }
+
+    match gst::glib::DateTime::now_local().and_then(|now| now.format(&expanded)) {
+        Ok(location) => location.to_string(),
+        Err(e) => {
+            tracing::warn!("Failed to expand {template}: {e}");
+            expanded
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SplitFile {
+    name: String,
+    config: Config,
+
+    video_queue: gst::Element,
+    parser: Option<gst::Element>,
+    splitmuxsink: gst::Element,
+}
+
+impl SplitFile {
+    pub fn new(name: String, config: Config, codec: Codec) -> anyhow::Result<Self> {
+        tracing::info!("Creating SplitFile output {}", &name);
+
+        let video_queue = gst_create_element("queue", &format!("output_{}_video_queue", &name))?;
+        video_queue.connect("overrun", false, move |_| {
+            tracing::warn!("SplitFile Video queue overrun");
+            None
+        });
+
+        let parser = gst_create_parser(codec, &format!("output_{}_parser", &name))?;
+        let muxer = gst_create_muxer(&config.muxer_config, &format!("output_{}_muxer", &name))?;
+
+        let splitmuxsink =
+            gst_create_element("splitmuxsink", &format!("output_{}_splitmuxsink", &name))?;
+        gst_set_property(&splitmuxsink, "muxer", &muxer)?;
+        gst_set_property(&splitmuxsink, "max-size-time", config.max_size_time)?;
+        gst_set_property(&splitmuxsink, "max-size-bytes", config.max_size_bytes)?;
+        // There is no time to request a keyframe at when splitting by size.
+        gst_set_property(
+            &splitmuxsink,
+            "send-keyframe-requests",
+            config.send_keyframe_requests && config.max_size_time != 0,
+        )?;
+        // splitmuxsink's own max-files wraps the fragment index around, which
+        // only removes old files if the names repeat. The template usually
+        // contains a date, so old fragments are removed here instead.
+        gst_set_property(&splitmuxsink, "max-files", 0u32)?;
+
+        let template = config.location.clone();
+        let max_files = config.max_files as usize;
+        let files: Arc<Mutex<VecDeque<PathBuf>>> = Arc::new(Mutex::new(VecDeque::new()));
+        splitmuxsink.connect("format-location", false, move |args| {
+            let fragment_id = args[1].get::<u32>().unwrap_or(0);
+            let location = format_location(&template, fragment_id);
+            tracing::info!("Opening fragment {location}");
+
+            if max_files > 0 {
+                let mut files = files.lock().unwrap_or_else(|e| e.into_inner());
+                files.push_back(PathBuf::from(&location));
+                while files.len() > max_files {
+                    if let Some(old) = files.pop_front() {
+                        tracing::info!("Removing fragment {}", old.display());
+                        if let Err(e) = std::fs::remove_file(&old) {
+                            tracing::warn!("Failed to remove {}: {e}", old.display());
+                        }
+                    }
+                }
+            }
+
+            Some(location.to_value())
+        });
+
+        Ok(SplitFile {
+            name,
+            config,
+            video_queue,
+            parser,
+            splitmuxsink,
+        })
+    }
+
+    /// The elements in the order they are linked.
+    pub fn elements(&self) -> Vec<gst::Element> {
+        std::iter::once(self.video_queue.clone())
+            .chain(self.parser.clone())
+            .chain(std::iter::once(self.splitmuxsink.clone()))
+            .collect()
+    }
+
+    /// Flushes a closed fragment to storage if `fsync` is enabled.
+    pub fn fragment_closed(&self, location: &str) -> anyhow::Result<()> {
+        if !self.config.fsync {
+            return Ok(());
+        }
+        tracing::info!("Syncing {location} to storage");
+        fsync_file(Path::new(location))
+    }
+}
+
+impl Drop for SplitFile {
+    fn drop(&mut self) {
+        tracing::info!("Dropping SplitFile {}", self.name);
+    }
+}
+
+impl Pipeline for SplitFile {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Linking {} output elements", self.name);
+        let elements = self.elements();
+        let elements = elements.iter().collect::<Vec<_>>();
+        pipeline.add_many(&elements).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to add elements to pipeline: {e}"))
+        })?;
+
+        gst::Element::link_many(&elements).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to link elements in pipeline: {e}"))
+        })?;
+
+        gst_sync_state_with_parent(&self.elements())?;
+
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Unlinking {} output elements", self.name);
+        let elements = self.elements();
+        pipeline
+            .remove_many(&elements.iter().collect::<Vec<_>>())
+            .map_err(|e| {
+                RecorderError::ElementError(format!("Failed to remove elements from pipeline: {e}"))
+            })?;
+        gst_set_null(&self.elements());
+
+        Ok(())
+    }
+}
+
+impl PipelineSink for SplitFile {
+    fn sink(&self) -> gst::Element {
+        self.video_queue.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_location_pads_the_fragment_id_to_the_width() {
+        assert_eq!(format_location("a-%05d.mkv", 7), "a-00007.mkv");
+        assert_eq!(format_location("a-%3d.mkv", 7), "a-  7.mkv");
+        assert_eq!(format_location("a-%02d.mkv", 12345), "a-12345.mkv");
+    }
+
+    #[test]
+    fn format_location_keeps_escaped_percent_signs() {
+        assert_eq!(format_location("%%", 7), "%");
+        assert_eq!(format_location("100%%-%02d.mkv", 7), "100%-07.mkv");
+        assert_eq!(format_location("%%05d.mkv", 7), "%05d.mkv");
+    }
+
+    #[test]
+    fn format_location_leaves_bare_d_to_the_date() {
+        let day = gst::glib::DateTime::now_local()
+            .and_then(|now| now.format("%d"))
+            .unwrap();
+        assert_eq!(format_location("%d-%04d.mkv", 7), format!("{day}-0007.mkv"));
+    }
+}
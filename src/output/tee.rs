@@ -0,0 +1,398 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::encoder::Codec;
+use crate::output::{Config, Output};
+use crate::recorder::errors::RecorderError;
+use crate::traits::*;
+use crate::util::{gst_create_element, gst_running_time};
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// What is needed to tell which output an element belongs to, without
+/// locking the pipeline. Used from the bus sync handler.
+#[derive(Debug, Clone)]
+pub struct BranchHandle {
+    pub name: String,
+    elements: Vec<gst::Element>,
+    failed: Arc<AtomicBool>,
+}
+
+impl BranchHandle {
+    pub fn contains(&self, object: &gst::Object) -> bool {
+        self.elements
+            .iter()
+            .any(|e| e.upcast_ref::<gst::Object>() == object || object.has_as_ancestor(e))
+    }
+
+    /// Drops all further buffers for the branch.
+    pub fn set_failed(&self) {
+        self.failed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+}
+
+/// Every branch the tee ever had, shared with the bus sync handler.
+pub type BranchHandles = Arc<Mutex<Vec<BranchHandle>>>;
+
+/// An output fed by one of the tee's src pads.
+#[derive(Debug)]
+struct Branch {
+    output: Output,
+    handle: BranchHandle,
+    tee_pad: Option<gst::Pad>,
+}
+
+impl Branch {
+    fn new(config: Config, codec: Codec) -> anyhow::Result<Self> {
+        let name = config.name.clone();
+        let output = Output::new(config, codec)?;
+        let handle = BranchHandle {
+            name,
+            elements: output.elements(),
+            failed: Arc::new(AtomicBool::new(false)),
+        };
+        Ok(Branch {
+            output,
+            handle,
+            tee_pad: None,
+        })
+    }
+}
+
+/// A branch that was sent EOS and is finishing its file.
+#[derive(Debug)]
+struct Detaching {
+    branch: Branch,
+    done: Sender<()>,
+}
+
+/// Feeds the encoded stream to all outputs. Each output is a branch that
+/// starts with its own queue, so a slow output doesn't hold back the others.
+#[derive(Debug)]
+pub struct Tee {
+    tee: gst::Element,
+    codec: Codec,
+    branches: Vec<Branch>,
+    detaching: Vec<Detaching>,
+    handles: BranchHandles,
+}
+
+impl Tee {
+    pub fn new(configs: Vec<Config>, codec: Codec) -> anyhow::Result<Self> {
+        let tee = gst_create_element("tee", "output_tee")?;
+        // A detached branch must not stop the others.
+        tee.set_property("allow-not-linked", true);
+
+        let branches = configs
+            .into_iter()
+            .map(|config| Branch::new(config, codec))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let handles = branches.iter().map(|b| b.handle.clone()).collect();
+
+        Ok(Tee {
+            tee,
+            codec,
+            branches,
+            detaching: vec![],
+            handles: Arc::new(Mutex::new(handles)),
+        })
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &Output> {
+        self.branches.iter().map(|b| &b.output)
+    }
+
+    /// Handles of all branches, including ones added or removed later.
+    pub fn handles(&self) -> BranchHandles {
+        self.handles.clone()
+    }
+
+    /// The branch `object` belongs to, if it belongs to one.
+    pub fn branch_of(&self, object: &gst::Object) -> Option<BranchHandle> {
+        let handles = self.handles.lock().unwrap_or_else(|e| e.into_inner());
+        handles.iter().find(|h| h.contains(object)).cloned()
+    }
+
+    pub fn output(&self, name: &str) -> Option<&Output> {
+        self.branches
+            .iter()
+            .find(|b| b.handle.name == name)
+            .map(|b| &b.output)
+    }
+
+    pub fn is_detaching(&self, name: &str) -> bool {
+        self.detaching.iter().any(|d| d.branch.handle.name == name)
+    }
+
+    /// Whether any attached branch is still getting buffers.
+    pub fn has_healthy_branches(&self) -> bool {
+        self.branches.iter().any(|b| !b.handle.is_failed())
+    }
+
+    /// Connects the tee to each output. Needs `link` first, so all elements
+    /// are in the pipeline.
+    pub fn connect(&mut self) -> anyhow::Result<()> {
+        for branch in &mut self.branches {
+            Self::link_branch(&self.tee, branch, false)?;
+        }
+
+        Ok(())
+    }
+
+    fn link_branch(
+        tee: &gst::Element,
+        branch: &mut Branch,
+        wait_for_keyframe: bool,
+    ) -> anyhow::Result<()> {
+        let name = &branch.handle.name;
+        let tee_pad = tee.request_pad_simple("src_%u").ok_or_else(|| {
+            RecorderError::ElementError(format!("Failed to request tee pad for output {name}"))
+        })?;
+        let sink_pad =
+            branch.output.sink().static_pad("sink").ok_or_else(|| {
+                RecorderError::ElementError(format!("Output {name} has no sink pad"))
+            })?;
+
+        // Once the branch failed its queue returns an error flow, which the
+        // tee would pass upstream and stop every output. Dropping the buffers
+        // here keeps the other branches running.
+        let handle = branch.handle.clone();
+        tee_pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+            move |_, _| {
+                if handle.is_failed() {
+                    gst::PadProbeReturn::Drop
+                } else {
+                    gst::PadProbeReturn::Ok
+                }
+            },
+        );
+        if wait_for_keyframe {
+            tee_pad.add_probe(gst::PadProbeType::BUFFER, start_at_keyframe);
+        }
+
+        if let Err(e) = tee_pad.link(&sink_pad) {
+            tee.release_request_pad(&tee_pad);
+            return Err(RecorderError::ElementError(format!(
+                "Error linking tee to output {name}: {e}"
+            ))
+            .into());
+        }
+
+        branch.tee_pad = Some(tee_pad);
+        Ok(())
+    }
+
+    /// Adds an output to the running pipeline. The output starts with the
+    /// next keyframe, which is requested from the encoder right away.
+    pub fn attach(&mut self, pipeline: &gst::Pipeline, config: Config) -> anyhow::Result<()> {
+        let mut branch = Branch::new(config, self.codec)?;
+        tracing::info!("Attaching output {}", branch.handle.name);
+
+        branch.output.link(pipeline)?;
+        if let Err(e) = Self::link_branch(&self.tee, &mut branch, true) {
+            let _ = branch.output.unlink(pipeline);
+            return Err(e);
+        }
+        if let Some(tee_pad) = &branch.tee_pad {
+            tee_pad.send_event(force_key_unit_event());
+        }
+
+        self.handles
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(branch.handle.clone());
+        self.branches.push(branch);
+        Ok(())
+    }
+
+    /// Sends EOS straight into every branch, past whatever is stuck
+    /// upstream, so the muxers can still finish their files. Each from its
+    /// own thread, the stream lock of a stuck branch may never be released.
+    pub fn salvage(&self) {
+        for branch in &self.branches {
+            let sink_pad = match branch.output.sink().static_pad("sink") {
+                Some(sink_pad) => sink_pad,
+                None => continue,
+            };
+            let name = branch.handle.name.clone();
+            std::thread::spawn(move || {
+                tracing::info!("Salvaging output {name}");
+                if !sink_pad.send_event(gst::event::Eos::new()) {
+                    tracing::warn!("Output {name} did not take the EOS");
+                }
+            });
+        }
+    }
+
+    /// Starts removing the output `name` from the running pipeline. The
+    /// branch is unlinked from the tee and gets its own EOS, so the muxer can
+    /// finish the file. The returned channel fires once `finish_detach` saw
+    /// the EOS at the end of the branch.
+    pub fn detach_gracefully(&mut self, name: &str) -> anyhow::Result<Receiver<()>> {
+        let index = self
+            .branches
+            .iter()
+            .position(|b| b.handle.name == name)
+            .ok_or_else(|| RecorderError::OutputNotFound(name.to_string()))?;
+        let mut branch = self.branches.remove(index);
+        tracing::info!("Detaching output {name}");
+
+        if let (Some(tee_pad), Some(sink_pad)) = (
+            branch.tee_pad.take(),
+            branch.output.sink().static_pad("sink"),
+        ) {
+            // Waits for the tee pad to be between two buffers, so the EOS is
+            // the last thing the branch gets.
+            let tee = self.tee.clone();
+            tee_pad.add_probe(gst::PadProbeType::IDLE, move |pad, _| {
+                let _ = pad.unlink(&sink_pad);
+                sink_pad.send_event(gst::event::Eos::new());
+                tee.release_request_pad(pad);
+                gst::PadProbeReturn::Remove
+            });
+        }
+
+        let (done, rx) = bounded(1);
+        self.detaching.push(Detaching { branch, done });
+        Ok(rx)
+    }
+
+    /// Removes a branch that was detached with `detach_gracefully` once the
+    /// sink at its end, `src`, posted EOS. Returns whether `src` belonged to
+    /// such a branch.
+    pub fn finish_detach(
+        &mut self,
+        pipeline: &gst::Pipeline,
+        src: &gst::Object,
+    ) -> anyhow::Result<bool> {
+        let index = match self
+            .detaching
+            .iter()
+            .position(|d| d.branch.handle.contains(src))
+        {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        let detaching = self.detaching.remove(index);
+        tracing::info!("Output {} finished", detaching.branch.handle.name);
+
+        // The sink handled the EOS, the file is complete.
+        if let Err(e) = detaching.branch.output.fsync() {
+            tracing::error!("{e}");
+        }
+        detaching.branch.output.unlink(pipeline)?;
+        let _ = detaching.done.send(());
+        Ok(true)
+    }
+
+    /// Removes the output `name` right away, without waiting for it to
+    /// finish. The other outputs are not interrupted.
+    pub fn detach(&mut self, pipeline: &gst::Pipeline, name: &str) -> anyhow::Result<()> {
+        if let Some(index) = self
+            .detaching
+            .iter()
+            .position(|d| d.branch.handle.name == name)
+        {
+            let detaching = self.detaching.remove(index);
+            tracing::info!("Dropping output {name}");
+            detaching.branch.handle.set_failed();
+            return detaching.branch.output.unlink(pipeline);
+        }
+
+        let index = self
+            .branches
+            .iter()
+            .position(|b| b.handle.name == name)
+            .ok_or_else(|| RecorderError::OutputNotFound(name.to_string()))?;
+        let branch = self.branches.remove(index);
+        tracing::info!("Detaching output {name}");
+
+        branch.handle.set_failed();
+        if let Some(tee_pad) = &branch.tee_pad {
+            let tee = self.tee.clone();
+            tee_pad.add_probe(gst::PadProbeType::IDLE, move |pad, _| {
+                if let Some(peer) = pad.peer() {
+                    let _ = pad.unlink(&peer);
+                }
+                tee.release_request_pad(pad);
+                gst::PadProbeReturn::Remove
+            });
+        }
+
+        branch.output.unlink(pipeline)
+    }
+}
+
+/// Drops delta frames until the first keyframe, then moves that keyframe to
+/// running time zero so the new file starts at the beginning. Setting the
+/// offset makes the pad resend the segment before the keyframe is pushed.
+fn start_at_keyframe(pad: &gst::Pad, info: &mut gst::PadProbeInfo) -> gst::PadProbeReturn {
+    let buffer = match &info.data {
+        Some(gst::PadProbeData::Buffer(buffer)) => buffer,
+        _ => return gst::PadProbeReturn::Ok,
+    };
+    if buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+        return gst::PadProbeReturn::Drop;
+    }
+
+    if let Some(running_time) = gst_running_time(pad, buffer) {
+        pad.set_offset(-(running_time.nseconds() as i64));
+    }
+    gst::PadProbeReturn::Remove
+}
+
+/// Upstream event asking the encoder for a keyframe as soon as possible.
+/// Same structure as `gst_video_event_new_upstream_force_key_unit`.
+fn force_key_unit_event() -> gst::Event {
+    let structure = gst::Structure::builder("GstForceKeyUnit")
+        .field("running-time", u64::MAX)
+        .field("all-headers", true)
+        .field("count", 0u32)
+        .build();
+    gst::event::CustomUpstream::new(structure)
+}
+
+impl Pipeline for Tee {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Linking output tee");
+        pipeline.add(&self.tee).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to add tee to pipeline: {e}"))
+        })?;
+        for branch in &self.branches {
+            branch.output.link(pipeline)?;
+        }
+
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Unlinking output tee");
+        for branch in &self.branches {
+            if let Some(tee_pad) = &branch.tee_pad {
+                self.tee.release_request_pad(tee_pad);
+            }
+            branch.output.unlink(pipeline)?;
+        }
+        for detaching in &self.detaching {
+            detaching.branch.output.unlink(pipeline)?;
+        }
+        pipeline.remove(&self.tee).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to remove tee from pipeline: {e}"))
+        })?;
+
+        Ok(())
+    }
+}
+
+impl PipelineSink for Tee {
+    fn sink(&self) -> gst::Element {
+        self.tee.clone()
+    }
+}
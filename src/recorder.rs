@@ -1,29 +1,68 @@
+mod duration;
 pub mod errors;
 mod gst_pipeline;
+mod pause;
+mod pre_roll;
+pub mod sessions;
+mod watchdog;
 
 use crate::encoder::Config as EncoderConfig;
-use crate::input::Config as InputConfig;
+use crate::input::{Config as InputConfig, Control};
 use crate::output::Config as OutputConfig;
+pub use watchdog::Config as WatchdogConfig;
 
 use self::gst_pipeline::{GstPipeline, PipelineState};
 use core::panic;
-use crossbeam_channel::{bounded, Receiver, Sender};
-use errors::RecorderError;
+use crossbeam_channel::Receiver;
+use errors::{FieldError, RecorderError, RecorderErrorLog};
 use gst::prelude::*;
 use gstreamer as gst;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
-use tokio::sync::oneshot;
+use std::time::Duration;
+use tokio::sync::{oneshot, watch};
+
+/// How long removing an output waits for it to finish its file.
+const OUTPUT_EOS_TIMEOUT: Duration = Duration::from_secs(10);
+/// Extra time a start or stop waits on top of the watchdog timeout, so the
+/// watchdog reports a stuck pipeline first.
+const TRANSITION_GRACE: Duration = Duration::from_secs(2);
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct Config {
     pub name: String,
     pub duration: Option<u64>,
+    /// Seconds of video from before the recording is started to include.
+    /// A config with pre-roll arms the recorder, the next start request
+    /// starts the recording.
+    pub pre_roll_seconds: u32,
     pub input: InputConfig,
-    pub output: OutputConfig,
+    /// Every output gets the same encoded stream. A single `output` object is
+    /// accepted as well.
+    #[serde(alias = "output", deserialize_with = "deserialize_outputs")]
+    pub outputs: Vec<OutputConfig>,
     pub encoder: EncoderConfig,
+    pub watchdog: WatchdogConfig,
+}
+
+fn deserialize_outputs<'de, D>(deserializer: D) -> Result<Vec<OutputConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Box<OutputConfig>),
+        Many(Vec<OutputConfig>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(output) => vec![*output],
+        OneOrMany::Many(outputs) => outputs,
+    })
 }
 
 impl Default for Config {
@@ -31,9 +70,11 @@ impl Default for Config {
         Config {
             name: "recorder".to_string(),
             duration: None,
+            pre_roll_seconds: 0,
             input: InputConfig::default(),
-            output: OutputConfig::default(),
+            outputs: vec![OutputConfig::default()],
             encoder: EncoderConfig::default(),
+            watchdog: WatchdogConfig::default(),
         }
     }
 }
@@ -48,10 +89,240 @@ impl Config {
         Config {
             name: "recorder".to_string(),
             duration: Some(duration),
+            pre_roll_seconds: 0,
             input,
-            output,
+            outputs: vec![output],
             encoder,
+            watchdog: WatchdogConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Checks the config before any element is created. Every problem found
+    /// is reported as a field level error.
+    pub fn validate(&self) -> Result<(), RecorderError> {
+        let mut errors = vec![];
+        self.input.validate("input", &mut errors);
+        self.encoder.validate("encoder", &mut errors);
+        if self.outputs.is_empty() {
+            errors.push(FieldError::new(
+                "outputs",
+                "at least one output is required",
+            ));
+        }
+        for (i, output) in self.outputs.iter().enumerate() {
+            output.validate(&format!("outputs[{i}]"), &mut errors);
+            if self.outputs[..i].iter().any(|o| o.name == output.name) {
+                errors.push(FieldError::new(
+                    format!("outputs[{i}].name"),
+                    format!("duplicate output name {}", output.name),
+                ));
+            }
+        }
+
+        // Any of the encoders may end up being used, so all of them have to
+        // fit every output.
+        let candidates = std::iter::once(("encoder.variant".to_string(), &self.encoder.variant))
+            .chain(
+                self.encoder
+                    .fallback
+                    .iter()
+                    .enumerate()
+                    .map(|(i, variant)| (format!("encoder.fallback[{i}]"), variant)),
+            );
+        for (field, variant) in candidates {
+            let codec = variant.codec();
+            for output in self.outputs.iter().filter(|o| !o.supports(codec)) {
+                errors.push(FieldError::new(
+                    field.clone(),
+                    format!("{codec} is not supported by output {}", output.name),
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(RecorderError::ValidationError(errors))
+        }
+    }
+}
+
+/// Why the last recording ended.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Stopped by a stop request.
+    Stop,
+    /// Completed by reaching its `duration`.
+    Duration,
+    Error,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Status {
+    pub state: PipelineState,
+    pub config: Option<Config>,
+    pub elapsed_ms: Option<u64>,
+    pub locations: Vec<String>,
+    pub stop_reason: Option<StopReason>,
+    pub last_error: Option<RecorderErrorLog>,
+}
+
+impl Status {
+    /// The camera the recorder is holding open.
+    pub fn device(&self) -> Option<&str> {
+        match self.state {
+            PipelineState::Stopped | PipelineState::Error => None,
+            _ => self.config.as_ref().and_then(|c| c.input.device()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Started,
+    Stopped,
+}
+
+/// A start or stop of a `Recorder`, or the removal of an output, that is
+/// still going on in the pipeline.
+pub struct Transition {
+    wait: Option<Wait>,
+}
+
+enum Wait {
+    State(TransitionWait),
+    OutputRemoval(OutputRemoval),
+}
+
+struct TransitionWait {
+    target: Target,
+    timeout: Option<Duration>,
+    state_rx: watch::Receiver<PipelineState>,
+    pipeline: Arc<Mutex<GstPipeline>>,
+    last_error: Arc<Mutex<Option<RecorderErrorLog>>>,
+}
+
+/// An output that was sent EOS and is finishing its file.
+struct OutputRemoval {
+    name: String,
+    done: Receiver<()>,
+    pipeline: Arc<Mutex<GstPipeline>>,
+}
+
+impl Transition {
+    /// A transition that completed right away.
+    fn done() -> Self {
+        Transition { wait: None }
+    }
+
+    /// Waits until the pipeline reached the target state, or failed. For an
+    /// output removal, until the output finished its file.
+    pub async fn wait(self) -> Result<(), RecorderErrorLog> {
+        match self.wait {
+            Some(Wait::State(wait)) => wait.wait().await,
+            Some(Wait::OutputRemoval(removal)) => removal.wait().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl TransitionWait {
+    async fn wait(self) -> Result<(), RecorderErrorLog> {
+        let TransitionWait {
+            target,
+            timeout,
+            mut state_rx,
+            pipeline,
+            last_error,
+        } = self;
+
+        let reached = async {
+            match target {
+                // The first change tells if the pipeline started. It may
+                // already be stopped again when this runs.
+                Target::Started => match state_rx.changed().await {
+                    Ok(()) => *state_rx.borrow(),
+                    Err(_) => PipelineState::Error,
+                },
+                Target::Stopped => {
+                    match state_rx
+                        .wait_for(|state| {
+                            matches!(state, PipelineState::Stopped | PipelineState::Error)
+                        })
+                        .await
+                    {
+                        Ok(state) => *state,
+                        Err(_) => PipelineState::Error,
+                    }
+                }
+            }
+        };
+        let reached = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, reached).await.ok(),
+            None => Some(reached.await),
+        };
+
+        match reached {
+            Some(PipelineState::Error) => {
+                tracing::error!("Pipeline failed while waiting to be {target:?}");
+                let last_error = match last_error.lock() {
+                    Ok(last_error) => last_error.clone(),
+                    Err(_) => None,
+                };
+                Err(last_error.unwrap_or_else(|| RecorderError::PipelineInErrorState.into()))
+            }
+            Some(state) => {
+                tracing::info!("Received state: {:?}", state);
+                Ok(())
+            }
+            None => {
+                let err = RecorderError::Timeout(format!(
+                    "Pipeline not {target:?} after {}s",
+                    timeout.unwrap_or_default().as_secs()
+                ));
+                tracing::error!("{err}");
+                let mut gst_pipeline = pipeline.lock().map_err(|e| {
+                    RecorderError::AppError(format!("Failed to lock pipeline: {e}"))
+                })?;
+                let log = Recorder::error_log(&mut gst_pipeline, err);
+                Recorder::store_error(&last_error, &log);
+                Err(log)
+            }
+        }
+    }
+}
+
+impl OutputRemoval {
+    /// Waits up to `OUTPUT_EOS_TIMEOUT` for the output to finish, then drops
+    /// it. The bus thread needs the pipeline to finish the output, so it is
+    /// only locked after waiting.
+    async fn wait(self) -> Result<(), RecorderErrorLog> {
+        let OutputRemoval {
+            name,
+            done,
+            pipeline,
+        } = self;
+        let finished = tokio::task::spawn_blocking(move || done.recv_timeout(OUTPUT_EOS_TIMEOUT))
+            .await
+            .map_err(|e| RecorderError::AppError(format!("Failed to wait for {name}: {e}")))?;
+        if finished.is_ok() {
+            return Ok(());
         }
+
+        tracing::warn!("Output {name} did not finish, removing it anyway");
+        let mut gst_pipeline = pipeline
+            .lock()
+            .map_err(|e| RecorderError::AppError(format!("Failed to lock pipeline: {e}")))?;
+        if let Err(e) = gst_pipeline.drop_output(&name) {
+            tracing::error!("Failed to remove output {name}: {e}");
+        }
+        Err(RecorderError::OutputError(format!(
+            "Output {name} did not finish within {}s, the file may be incomplete",
+            OUTPUT_EOS_TIMEOUT.as_secs()
+        ))
+        .into())
     }
 }
 
@@ -59,7 +330,9 @@ pub struct Recorder {
     pipeline: Option<Arc<Mutex<GstPipeline>>>,
     // join_handle: Option<tokio::task::JoinHandle<()>>,
     join_handle: Option<std::thread::JoinHandle<()>>,
-    state_rx: Mutex<Option<Receiver<PipelineState>>>,
+    state_rx: Mutex<Option<watch::Receiver<PipelineState>>>,
+    last_error: Arc<Mutex<Option<RecorderErrorLog>>>,
+    stop_reason: Arc<Mutex<Option<StopReason>>>,
 }
 
 impl Recorder {
@@ -68,106 +341,378 @@ impl Recorder {
             pipeline: None,
             join_handle: None,
             state_rx: Mutex::new(None),
+            last_error: Arc::new(Mutex::new(None)),
+            stop_reason: Arc::new(Mutex::new(None)),
         })
     }
 
-    pub fn start(&mut self, config: Config) -> Result<(), RecorderError> {
+    /// Starts a recording. A config with `pre_roll_seconds` only arms the
+    /// recorder, and starting an armed recorder triggers it, see `trigger`.
+    /// The returned `Transition` completes once the pipeline is PLAYING.
+    pub fn start(&mut self, config: Config) -> Result<Transition, RecorderErrorLog> {
         tracing::info!("Starting recorder: {:?}", &config);
+        if self.get_state()? == PipelineState::Armed {
+            tracing::info!("Recorder is armed, ignoring the config");
+            self.trigger().map_err(|e| self.set_last_error(e.into()))?;
+            return Ok(Transition::done());
+        }
+        self.release_finished();
         if self.pipeline.is_some() {
             return Err(RecorderError::AppError(
                 "Recorder already started".to_string(),
             ))?;
         }
 
-        let duration = config.duration.unwrap_or(0);
+        if let Err(e) = config.validate() {
+            return Err(self.set_last_error(e.into()));
+        }
+
+        Recorder::store_stop_reason(&self.stop_reason, None);
+        let pre_roll = config.pre_roll_seconds > 0;
+        let timeout = config
+            .watchdog
+            .start_timeout()
+            .map(|timeout| timeout + TRANSITION_GRACE);
 
-        let gst_pipeline = GstPipeline::new(config).map_err(|e| {
+        let mut gst_pipeline = GstPipeline::new(config).map_err(|e| {
             tracing::error!("Failed to create GstPipeline: {e}");
-            return RecorderError::AppError(format!("Failed to create GstPipeline: {e}"));
+            let err = match e.downcast::<RecorderError>() {
+                Ok(err) => err,
+                Err(e) => RecorderError::AppError(format!("Failed to create GstPipeline: {e}")),
+            };
+            self.set_last_error(err.into())
         })?;
 
-        gst_pipeline.link_pipelines().map_err(|e| {
+        if let Err(e) = gst_pipeline.link_pipelines() {
             tracing::error!("Failed to link pipeline: {e}");
-            return RecorderError::AppError(format!("Failed to link pipeline: {e}"));
-        })?;
+            return Err(self.pipeline_error(
+                &mut gst_pipeline,
+                RecorderError::AppError(format!("Failed to link pipeline: {e}")),
+            ));
+        }
 
-        gst_pipeline.connect_pipelines().map_err(|e| {
+        if let Err(e) = gst_pipeline.connect_pipelines() {
             tracing::error!("Failed to connect pipeline: {e}");
-            return RecorderError::AppError(format!("Failed to connect pipeline: {e}"));
-        })?;
+            return Err(self.pipeline_error(
+                &mut gst_pipeline,
+                RecorderError::AppError(format!("Failed to connect pipeline: {e}")),
+            ));
+        }
+
+        // Set before the watchdog looks at the pipeline.
+        let armed = if pre_roll {
+            gst_pipeline.arm()
+        } else {
+            gst_pipeline.state.set_state(PipelineState::Starting)
+        };
+        if let Err(e) = armed {
+            return Err(self.pipeline_error(&mut gst_pipeline, e));
+        }
 
         self.pipeline = Some(Arc::new(Mutex::new(gst_pipeline)));
         self.maybe_start_thread();
-        self.change_pipeline_state(gst::State::Playing)?;
-
-        let result = self.state_rx.lock();
-        if let Ok(rx) = result {
-            if let Some(rx) = rx.as_ref() {
-                match rx.recv() {
-                    Ok(state) => {
-                        tracing::info!("Received state: {:?}", state);
-                    }
-                    Err(e) => {
-                        tracing::error!("Error during state transition: {:?}", e);
-                        //TODO: Should we stop the pipeline here?
-                        return Err(RecorderError::StateError(format!(
-                            "Error during state transition: {:?}",
-                            e
-                        )));
-                    }
-                }
-            }
+        let transition = self.transition(Target::Started, timeout)?;
+        if let Err(e) = self.change_pipeline_state(gst::State::Playing) {
+            let mut gst_pipeline = self.lock_pipeline()?;
+            return Err(self.pipeline_error(&mut gst_pipeline, e));
+        }
+
+        // The outputs don't get any data until the trigger, so they may not
+        // reach PLAYING before that.
+        if pre_roll {
+            tracing::info!("Recorder armed");
+            return Ok(Transition::done());
         }
 
-        Ok(())
+        Ok(transition)
     }
 
-    pub fn stop(&mut self) -> Result<(), RecorderError> {
+    /// Sends EOS to finish the files. The returned `Transition` completes
+    /// once the EOS went through the pipeline.
+    pub fn stop(&mut self) -> Result<Transition, RecorderError> {
         tracing::info!("Stopping recorder");
         if self.pipeline.is_none() {
             return Err(RecorderError::AppError(
                 "Recorder already stopped".to_string(),
             ));
         }
+        // Completed by its duration, or failed.
+        if matches!(
+            self.get_state()?,
+            PipelineState::Stopped | PipelineState::Error
+        ) {
+            self.release_finished();
+            return Ok(Transition::done());
+        }
 
-        let pipeline = self.lock_pipeline()?.pipeline.clone();
+        let timeout = self
+            .lock_pipeline()?
+            .config
+            .watchdog
+            .stop_timeout()
+            .map(|timeout| timeout + TRANSITION_GRACE);
+        let transition = self.transition(Target::Stopped, timeout)?;
+        if self.get_state()? == PipelineState::Stopping {
+            return Ok(transition);
+        }
+        let pipeline = {
+            let mut gst_pipeline = self.lock_pipeline()?;
+            if gst_pipeline.get_state() == PipelineState::Armed {
+                gst_pipeline.disarm()?;
+            }
+            gst_pipeline.cancel_duration();
+            gst_pipeline.state.set_state(PipelineState::Stopping)?;
+            gst_pipeline.pipeline.clone()
+        };
         pipeline.send_event(gst::event::Eos::new());
 
-        //Wait for EOS to complete.
-        let result = self.state_rx.lock();
-        if let Ok(rx) = result {
-            if let Some(rx) = rx.as_ref() {
-                match rx.recv() {
-                    Ok(state) => {
-                        tracing::info!("Received state: {:?}", state);
-                    }
-                    Err(e) => {
-                        tracing::error!("Error during state transition: {:?}", e);
-                    }
-                }
-            }
+        // The pipeline is released by the next start, the bus thread
+        // already set it to NULL.
+        Ok(transition)
+    }
+
+    /// Starts the recording of an armed recorder. The recording begins with
+    /// up to `pre_roll_seconds` of video from before this call.
+    pub fn trigger(&mut self) -> Result<(), RecorderError> {
+        tracing::info!("Triggering recorder");
+        self.lock_pipeline()?.trigger()
+    }
+
+    pub fn get_state(&self) -> Result<PipelineState, RecorderError> {
+        if self.pipeline.is_none() {
+            return Ok(PipelineState::Stopped);
         }
+        Ok(self.lock_pipeline()?.get_state())
+    }
 
-        match self.join_handle.take() {
-            Some(handle) => match handle.join() {
-                Ok(_) => {
-                    tracing::info!("Join handle completed");
-                }
-                Err(e) => {
-                    tracing::error!("Join handle failed: {:?}", e);
-                }
-            },
-            None => {
-                tracing::error!("Join handle is None");
+    pub fn status(&self) -> Result<Status, RecorderError> {
+        let last_error = self.last_error();
+        let stop_reason = self.stop_reason();
+        if self.pipeline.is_none() {
+            return Ok(Status {
+                state: PipelineState::Stopped,
+                config: None,
+                elapsed_ms: None,
+                locations: vec![],
+                stop_reason,
+                last_error,
+            });
+        }
+
+        let gst_pipeline = self.lock_pipeline()?;
+        Ok(Status {
+            state: gst_pipeline.get_state(),
+            config: Some(gst_pipeline.config.clone()),
+            elapsed_ms: gst_pipeline.running_time().map(|t| t.mseconds()),
+            locations: gst_pipeline
+                .config
+                .outputs
+                .iter()
+                .filter_map(|o| o.location())
+                .collect(),
+            stop_reason,
+            last_error,
+        })
+    }
+
+    /// Pauses the recording. The input keeps running, nothing is recorded
+    /// until `resume`.
+    pub fn pause(&mut self) -> Result<(), RecorderError> {
+        tracing::info!("Pausing recorder");
+        if self.get_state()? != PipelineState::Playing {
+            return Err(RecorderError::StateError(
+                "Only a running recording can be paused".to_string(),
+            ));
+        }
+        self.lock_pipeline()?.pause()
+    }
+
+    /// Continues a paused recording in the same files. The paused time is
+    /// left out of the recording.
+    pub fn resume(&mut self) -> Result<(), RecorderError> {
+        tracing::info!("Resuming recorder");
+        if self.get_state()? != PipelineState::Paused {
+            return Err(RecorderError::StateError(
+                "Only a paused recording can be resumed".to_string(),
+            ));
+        }
+        self.lock_pipeline()?.resume()
+    }
+
+    /// Whether the recording that is running reads from the input `name`.
+    pub fn has_input(&self, name: &str) -> Result<bool, RecorderError> {
+        if self.pipeline.is_none() {
+            return Ok(false);
+        }
+        let gst_pipeline = self.lock_pipeline()?;
+        Ok(!matches!(
+            gst_pipeline.get_state(),
+            PipelineState::Stopped | PipelineState::Error
+        ) && gst_pipeline.config.input.name == name)
+    }
+
+    /// The camera controls of the running recording's input.
+    pub fn controls(&self) -> Result<Vec<Control>, RecorderError> {
+        self.lock_pipeline()?.controls()
+    }
+
+    /// Sets camera controls of the running recording's input. Returns all
+    /// controls with their new values.
+    pub fn set_controls(
+        &mut self,
+        values: &BTreeMap<String, i32>,
+    ) -> Result<Vec<Control>, RecorderError> {
+        tracing::info!("Setting controls {values:?}");
+        self.lock_pipeline()?.set_controls(values)
+    }
+
+    /// Adds an output to the running recording.
+    pub fn add_output(&mut self, config: OutputConfig) -> Result<(), RecorderError> {
+        let mut gst_pipeline = self.lock_pipeline()?;
+        if !gst_pipeline.is_recording() {
+            return Err(RecorderError::StateError(
+                "Outputs can only be added while recording".to_string(),
+            ));
+        }
+        gst_pipeline.add_output(config)
+    }
+
+    /// Starts removing an output from the running recording. The returned
+    /// `Transition` completes once the output finished its file, or after
+    /// `OUTPUT_EOS_TIMEOUT`.
+    pub fn remove_output(&mut self, name: &str) -> Result<Transition, RecorderError> {
+        let mut gst_pipeline = self.lock_pipeline()?;
+        if !gst_pipeline.is_recording() {
+            return Err(RecorderError::StateError(
+                "Outputs can only be removed while recording".to_string(),
+            ));
+        }
+        let done = gst_pipeline.remove_output(name)?;
+
+        Ok(Transition {
+            wait: Some(Wait::OutputRemoval(OutputRemoval {
+                name: name.to_string(),
+                done,
+                pipeline: self
+                    .pipeline
+                    .clone()
+                    .ok_or_else(|| RecorderError::AppError("Pipeline is None".to_string()))?,
+            })),
+        })
+    }
+
+    /// Releases a pipeline that ended on its own, e.g. by reaching its
+    /// duration, so a new recording can be started.
+    fn release_finished(&mut self) {
+        // After an error the bus thread may still be running.
+        let finished = match &self.join_handle {
+            Some(handle) => handle.is_finished(),
+            None => true,
+        };
+        match self.lock_pipeline().map(|p| p.get_state()) {
+            Ok(PipelineState::Stopped) => {}
+            Ok(PipelineState::Error) if finished => {}
+            _ => return,
+        }
+
+        tracing::info!("Releasing finished pipeline");
+        if let Some(handle) = self.join_handle.take() {
+            if let Err(e) = handle.join() {
+                tracing::error!("Join handle failed: {:?}", e);
             }
         }
-        self.join_handle = None;
         self.pipeline = None;
-        Ok(())
     }
 
-    pub fn get_state(&self) -> Result<PipelineState, RecorderError> {
-        Err(RecorderError::AppError("Not implemented".to_string()))
+    fn transition(
+        &self,
+        target: Target,
+        timeout: Option<Duration>,
+    ) -> Result<Transition, RecorderError> {
+        let state_rx = match self.state_rx.lock() {
+            Ok(state_rx) => state_rx.clone(),
+            Err(e) => {
+                return Err(RecorderError::AppError(format!(
+                    "Failed to lock state_rx: {e}"
+                )))
+            }
+        };
+        let state_rx = state_rx
+            .ok_or_else(|| RecorderError::AppError("Bus thread is not running".to_string()))?;
+        let pipeline = self
+            .pipeline
+            .clone()
+            .ok_or_else(|| RecorderError::AppError("Pipeline is None".to_string()))?;
+        Ok(Transition {
+            wait: Some(Wait::State(TransitionWait {
+                target,
+                timeout,
+                state_rx,
+                pipeline,
+                last_error: self.last_error.clone(),
+            })),
+        })
+    }
+
+    fn stop_reason(&self) -> Option<StopReason> {
+        match self.stop_reason.lock() {
+            Ok(stop_reason) => *stop_reason,
+            Err(e) => {
+                tracing::error!("Failed to lock stop reason: {e}");
+                None
+            }
+        }
+    }
+
+    fn store_stop_reason(stop_reason: &Mutex<Option<StopReason>>, reason: Option<StopReason>) {
+        match stop_reason.lock() {
+            Ok(mut stop_reason) => *stop_reason = reason,
+            Err(e) => tracing::error!("Failed to lock stop reason: {e}"),
+        }
+    }
+
+    fn last_error(&self) -> Option<RecorderErrorLog> {
+        match self.last_error.lock() {
+            Ok(last_error) => last_error.clone(),
+            Err(e) => {
+                tracing::error!("Failed to lock last error: {e}");
+                None
+            }
+        }
+    }
+
+    fn set_last_error(&self, log: RecorderErrorLog) -> RecorderErrorLog {
+        Recorder::store_error(&self.last_error, &log);
+        log
+    }
+
+    /// Moves `gst_pipeline` into the error state and records `err` together
+    /// with a dump of the pipeline graph at the time of the error.
+    fn pipeline_error(
+        &self,
+        gst_pipeline: &mut GstPipeline,
+        err: RecorderError,
+    ) -> RecorderErrorLog {
+        let log = Recorder::error_log(gst_pipeline, err);
+        self.set_last_error(log)
+    }
+
+    fn error_log(gst_pipeline: &mut GstPipeline, err: RecorderError) -> RecorderErrorLog {
+        if let Err(e) = gst_pipeline.state.set_state(PipelineState::Error) {
+            tracing::warn!("{e}");
+        }
+        RecorderErrorLog::new(
+            gst_pipeline.get_state(),
+            Some(err.to_string()),
+            Some(gst_pipeline.debug_graph()),
+        )
+    }
+
+    fn store_error(last_error: &Mutex<Option<RecorderErrorLog>>, log: &RecorderErrorLog) {
+        match last_error.lock() {
+            Ok(mut last_error) => *last_error = Some(log.clone()),
+            Err(e) => tracing::error!("Failed to lock last error: {e}"),
+        }
     }
 
     fn lock_pipeline(&self) -> Result<std::sync::MutexGuard<GstPipeline>, RecorderError> {
@@ -195,12 +740,9 @@ impl Recorder {
         }
     }
 
-    fn _debug_pipeline(&self) -> Result<(), RecorderError> {
+    fn _debug_pipeline(&self) -> Result<String, RecorderError> {
         let gst_pipeline = self.lock_pipeline()?;
-        gst_pipeline
-            .pipeline
-            .debug_to_dot_file(gst::DebugGraphDetails::all(), "pipeline-playing");
-        Ok(())
+        Ok(gst_pipeline.debug_graph())
     }
 
     fn maybe_start_thread(&mut self) {
@@ -244,11 +786,8 @@ impl Recorder {
             }
         });
 
-        // :WARNING:
-        // Given this is bounded size 1 there has to be a one to one
-        // relationship between the sender and receiver. If the sender
-        // fails to send the receiver will block forever.
-        let (state_tx, state_rx) = bounded(1);
+        let (state_tx, state_rx) = watch::channel(PipelineState::Stopped);
+        let state_tx = Arc::new(state_tx);
         let mutex = self.state_rx.lock();
 
         //If we can not lock the mutex then we are in a bad state.
@@ -259,15 +798,35 @@ impl Recorder {
         *mutex.unwrap() = Some(state_rx);
 
         let pipe = self.pipeline.as_mut().unwrap().clone();
+        let last_error = self.last_error.clone();
+        let stop_reason = self.stop_reason.clone();
+        let watchdog_config = match pipe.lock() {
+            Ok(gst_pipeline) => gst_pipeline.config.watchdog.clone(),
+            Err(e) => {
+                tracing::error!("Failed to lock pipeline: {e}");
+                WatchdogConfig::default()
+            }
+        };
+        // Runs until the pipeline stopped or failed, it needs no handle.
+        watchdog::Watchdog::new(
+            watchdog_config,
+            pipe.clone(),
+            state_tx.clone(),
+            last_error.clone(),
+            stop_reason.clone(),
+        )
+        .spawn();
         self.join_handle = Some(std::thread::spawn(move || {
-            Recorder::watch_bus(pipe, bus_tx, state_tx)
+            Recorder::watch_bus(pipe, bus_tx, state_tx, last_error, stop_reason)
         }));
     }
 
     fn watch_bus(
         pipeline: Arc<Mutex<GstPipeline>>,
         bus_tx: oneshot::Sender<Result<(), RecorderError>>,
-        state_tx: Sender<PipelineState>,
+        state_tx: Arc<watch::Sender<PipelineState>>,
+        last_error: Arc<Mutex<Option<RecorderErrorLog>>>,
+        stop_reason: Arc<Mutex<Option<StopReason>>>,
     ) {
         tracing::info!("Watching Recorder pipeline bus");
 
@@ -277,30 +836,128 @@ impl Recorder {
 
         for msg in bus.iter_timed(gst::ClockTime::NONE) {
             //Note: If we have lots of messages this lock could become an issue.
-            let gst_pipeline = pipeline.lock();
-            if let Err(e) = gst_pipeline {
-                tracing::error!("Failed to lock pipeline {e}");
-                continue;
-            }
+            let mut gst_pipeline = match pipeline.lock() {
+                Ok(gst_pipeline) => gst_pipeline,
+                Err(e) => {
+                    tracing::error!("Failed to lock pipeline {e}");
+                    continue;
+                }
+            };
 
             use gst::MessageView;
             match msg.view() {
                 MessageView::Eos(..) => {
                     tracing::info!("GST Pipline received EOS");
-                    let _ = state_tx.send(PipelineState::Stopped);
+                    // The sinks are done with the file once the EOS message
+                    // is posted, so it is complete at this point.
+                    if let Err(e) = gst_pipeline.fsync_outputs() {
+                        tracing::error!("{e}");
+                    }
+                    gst_pipeline.eos_received = true;
+                    // The watchdog already reported the error and salvaged
+                    // the outputs, the recording stays failed.
+                    let salvaged = gst_pipeline.get_state() == PipelineState::Error;
+                    if !salvaged {
+                        let reason = if gst_pipeline.completed_by_duration() {
+                            StopReason::Duration
+                        } else {
+                            StopReason::Stop
+                        };
+                        Recorder::store_stop_reason(&stop_reason, Some(reason));
+                        if let Err(e) = gst_pipeline.state.set_state(PipelineState::Stopped) {
+                            tracing::warn!("{e}");
+                        }
+                    }
+                    // Nobody may call stop after a recording completed by
+                    // its duration, release the input right away.
+                    if let Err(e) = gst_pipeline.set_state(gst::State::Null) {
+                        tracing::error!("{e}");
+                    }
+                    if !salvaged {
+                        state_tx.send_replace(PipelineState::Stopped);
+                    }
                     let _ = bus_tx.send(Ok(()));
-                    let state = gst_pipeline.unwrap().pipeline.state(gst::ClockTime::NONE);
+                    let state = gst_pipeline.pipeline.state(gst::ClockTime::NONE);
                     tracing::info!("Pipeline current state: {:?}", state);
                     break;
                 }
+                MessageView::Error(err)
+                    if err
+                        .src()
+                        .and_then(|src| gst_pipeline.isolate_error(&src))
+                        .is_some() =>
+                {
+                    // Only the output the error came from is gone, the
+                    // others keep recording.
+                    let src = err.src().map(|s| s.path_string().to_string());
+                    let err_str = format!("Output failed: {:?}: {}", src, err.error());
+                    tracing::error!("{err_str}");
+                    let log = RecorderErrorLog::new(
+                        gst_pipeline.get_state(),
+                        Some(err_str),
+                        Some(gst_pipeline.debug_graph()),
+                    );
+                    Recorder::store_error(&last_error, &log);
+                }
                 MessageView::Error(err) => {
-                    let err_str = err.error().to_string();
+                    let src = err.src().map(|s| s.path_string().to_string());
+                    let err_str = match err.debug() {
+                        Some(debug) => format!("{:?}: {} ({})", src, err.error(), debug),
+                        None => format!("{:?}: {}", src, err.error()),
+                    };
                     tracing::error!("{}", err_str);
 
-                    let _ = state_tx.send(PipelineState::Error);
-                    let _ = bus_tx.send(Err(RecorderError::ElementError(err_str)));
+                    let error = RecorderError::ElementError(err_str);
+                    let log = Recorder::error_log(&mut gst_pipeline, error.clone());
+                    Recorder::store_error(&last_error, &log);
+                    Recorder::store_stop_reason(&stop_reason, Some(StopReason::Error));
+
+                    state_tx.send_replace(PipelineState::Error);
+                    let _ = bus_tx.send(Err(error));
                     break;
                 }
+                MessageView::Application(application)
+                    if application
+                        .structure()
+                        .map(|s| s.name() == watchdog::RELEASE_MESSAGE)
+                        .unwrap_or(false) =>
+                {
+                    tracing::warn!("Watchdog released the pipeline");
+                    let _ = bus_tx.send(Err(RecorderError::PipelineInErrorState));
+                    break;
+                }
+                MessageView::SegmentDone(..) => {
+                    if let Err(e) = gst_pipeline.segment_done() {
+                        tracing::error!("{e}");
+                    }
+                }
+                MessageView::Element(element) => {
+                    let forwarded = element
+                        .structure()
+                        .filter(|s| s.name() == "GstBinForwarded")
+                        .and_then(|s| s.get::<gst::Message>("message").ok());
+                    if let Some(forwarded) = forwarded {
+                        if let (MessageView::Eos(..), Some(src)) =
+                            (forwarded.view(), forwarded.src())
+                        {
+                            if let Err(e) = gst_pipeline.output_eos(&src) {
+                                tracing::error!("{e}");
+                            }
+                        }
+                        continue;
+                    }
+
+                    let location = element
+                        .structure()
+                        .filter(|s| s.name() == "splitmuxsink-fragment-closed")
+                        .and_then(|s| s.get::<String>("location").ok());
+                    if let (Some(location), Some(src)) = (location, element.src()) {
+                        tracing::info!("Fragment {location} closed");
+                        if let Err(e) = gst_pipeline.fragment_closed(&src, &location) {
+                            tracing::error!("{e}");
+                        }
+                    }
+                }
                 MessageView::StateChanged(state_changed) => {
                     let src = state_changed.src();
 
@@ -331,7 +988,16 @@ impl Recorder {
                                         gst::State::Playing,
                                         pending
                                     );
-                                    let _ = state_tx.send(PipelineState::Playing);
+                                    // Only `start` waits for this. An armed
+                                    // pipeline is started by the trigger.
+                                    if gst_pipeline.get_state() == PipelineState::Starting {
+                                        if let Err(e) =
+                                            gst_pipeline.state.set_state(PipelineState::Playing)
+                                        {
+                                            tracing::warn!("{e}");
+                                        }
+                                        state_tx.send_replace(PipelineState::Playing);
+                                    }
                                 }
                                 gst::State::Paused => {
                                     tracing::info!(
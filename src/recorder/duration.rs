@@ -0,0 +1,73 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::util::gst_running_time;
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Ends the recording after `duration` of running time by sending EOS into
+/// the encoder. Buffers past the limit are dropped, so the recording is cut
+/// at exactly that duration. The paused time doesn't count, the pause gate
+/// is upstream.
+#[derive(Debug)]
+pub struct DurationLimit {
+    cancelled: Arc<AtomicBool>,
+    completed: Arc<AtomicBool>,
+}
+
+impl DurationLimit {
+    /// Installs the limit on `pad`, the sink pad of the encoder. It counts
+    /// from running time `start`, or from the first buffer if `None`.
+    pub fn new(pad: &gst::Pad, duration: gst::ClockTime, start: Option<gst::ClockTime>) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let completed = Arc::new(AtomicBool::new(false));
+
+        let end = Mutex::new(start.map(|start| start + duration));
+        let probe_cancelled = cancelled.clone();
+        let probe_completed = completed.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            if probe_completed.load(Ordering::SeqCst) {
+                return gst::PadProbeReturn::Drop;
+            }
+            if probe_cancelled.load(Ordering::SeqCst) {
+                return gst::PadProbeReturn::Remove;
+            }
+            let running_time = match &info.data {
+                Some(gst::PadProbeData::Buffer(buffer)) => gst_running_time(pad, buffer),
+                _ => None,
+            };
+            let running_time = match running_time {
+                Some(running_time) => running_time,
+                None => return gst::PadProbeReturn::Ok,
+            };
+
+            let mut end = end.lock().unwrap_or_else(|e| e.into_inner());
+            let end = *end.get_or_insert(running_time + duration);
+            if running_time < end {
+                return gst::PadProbeReturn::Ok;
+            }
+
+            tracing::info!("Recording reached its duration of {duration}");
+            probe_completed.store(true, Ordering::SeqCst);
+            pad.send_event(gst::event::Eos::new());
+            gst::PadProbeReturn::Drop
+        });
+
+        DurationLimit {
+            cancelled,
+            completed,
+        }
+    }
+
+    /// Keeps the limit from ending the recording, e.g. because it is being
+    /// stopped by hand.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the recording was ended by the limit.
+    pub fn completed(&self) -> bool {
+        self.completed.load(Ordering::SeqCst)
+    }
+}
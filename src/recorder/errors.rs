@@ -1,25 +1,54 @@
 use serde::Serialize;
 
-// use super::gst_pipeline::PipelineState;
+use super::gst_pipeline::PipelineState;
 use gstreamer as gst;
 
+/// A problem with one field of a request, e.g.
+/// `{"field": "output.variant.FileSink.location", "message": "must not be empty"}`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct RecorderErrorLog {
-    // pub state: PipelineState,
+    pub state: PipelineState,
     pub error_message: Option<String>,
     pub error_pipeline_graph: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub field_errors: Vec<FieldError>,
+    #[serde(skip)]
+    pub status_code: u16,
 }
 
 impl RecorderErrorLog {
     pub fn new(
-        // state: PipelineState,
+        state: PipelineState,
         error_message: Option<String>,
         error_pipeline_graph: Option<String>,
     ) -> Self {
         Self {
-            // state,
+            state,
             error_message,
             error_pipeline_graph,
+            field_errors: vec![],
+            status_code: 500,
         }
     }
 }
@@ -27,9 +56,14 @@ impl RecorderErrorLog {
 impl From<RecorderError> for RecorderErrorLog {
     fn from(err: RecorderError) -> Self {
         Self {
-            // state: PipelineState::Error,
+            state: PipelineState::Error,
             error_message: Some(err.to_string()),
             error_pipeline_graph: None,
+            field_errors: match &err {
+                RecorderError::ValidationError(errors) => errors.clone(),
+                _ => vec![],
+            },
+            status_code: err.status_code(),
         }
     }
 }
@@ -50,17 +84,56 @@ pub enum RecorderError {
     OutputError(String),
     #[error("State error: {0}")]
     StateError(String),
+    #[error("Timeout: {0}")]
+    Timeout(String),
     #[error("Application Error: {0}")]
     AppError(String),
+    #[error("Session not found: {0}")]
+    SessionNotFound(String),
+    #[error("Output not found: {0}")]
+    OutputNotFound(String),
+    #[error("Input not found: {0}")]
+    InputNotFound(String),
+    #[error("Operation not found: {0}")]
+    OperationNotFound(String),
+    #[error("Session already running: {0}")]
+    SessionExists(String),
+    #[error("Preset not found: {0}")]
+    PresetNotFound(String),
+    #[error("Config Error: {0}")]
+    ConfigError(String),
+    #[error("Encoder not available: {0}")]
+    EncoderUnavailable(String),
+    #[error("Invalid config: {}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))]
+    ValidationError(Vec<FieldError>),
 }
 
 impl RecorderError {
+    /// HTTP status code used when the error is returned to a client.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Self::PresetNotFound(_)
+            | Self::ConfigError(_)
+            | Self::EncoderUnavailable(_)
+            | Self::ValidationError(_) => 400,
+            Self::SessionNotFound(_)
+            | Self::OutputNotFound(_)
+            | Self::InputNotFound(_)
+            | Self::OperationNotFound(_) => 404,
+            Self::SessionExists(_) => 409,
+            _ => 500,
+        }
+    }
+
     pub fn with_trace(self) -> Self {
         match self.clone() {
             Self::OutputError(msg)
             | Self::ElementError(msg)
             | Self::StateError(msg)
+            | Self::Timeout(msg)
             | Self::AppError(msg)
+            | Self::ConfigError(msg)
+            | Self::EncoderUnavailable(msg)
             | Self::InputError(msg) => {
                 tracing::error!(msg);
             }
@@ -73,7 +146,81 @@ impl RecorderError {
             Self::PipelineLockError => {
                 tracing::error!("Error locking pipeline");
             }
+            Self::SessionNotFound(id) => {
+                tracing::error!("Session not found: {}", id);
+            }
+            Self::SessionExists(id) => {
+                tracing::error!("Session already running: {}", id);
+            }
+            Self::OutputNotFound(name) => {
+                tracing::error!("Output not found: {}", name);
+            }
+            Self::InputNotFound(name) => {
+                tracing::error!("Input not found: {}", name);
+            }
+            Self::OperationNotFound(id) => {
+                tracing::error!("Operation not found: {}", id);
+            }
+            Self::PresetNotFound(name) => {
+                tracing::error!("Preset not found: {}", name);
+            }
+            Self::ValidationError(errors) => {
+                for error in errors {
+                    tracing::error!("Invalid config: {}", error);
+                }
+            }
         }
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_code_maps_client_errors() {
+        let field_errors = vec![FieldError::new("output", "must not be empty")];
+        assert_eq!(RecorderError::PresetNotFound("p".into()).status_code(), 400);
+        assert_eq!(RecorderError::ConfigError("c".into()).status_code(), 400);
+        assert_eq!(
+            RecorderError::EncoderUnavailable("e".into()).status_code(),
+            400
+        );
+        assert_eq!(
+            RecorderError::ValidationError(field_errors).status_code(),
+            400
+        );
+        assert_eq!(
+            RecorderError::SessionNotFound("s".into()).status_code(),
+            404
+        );
+        assert_eq!(RecorderError::OutputNotFound("o".into()).status_code(), 404);
+        assert_eq!(RecorderError::InputNotFound("i".into()).status_code(), 404);
+        assert_eq!(
+            RecorderError::OperationNotFound("o".into()).status_code(),
+            404
+        );
+        assert_eq!(RecorderError::SessionExists("s".into()).status_code(), 409);
+    }
+
+    #[test]
+    fn status_code_defaults_to_internal_error() {
+        assert_eq!(RecorderError::AppError("a".into()).status_code(), 500);
+        assert_eq!(RecorderError::Timeout("t".into()).status_code(), 500);
+        assert_eq!(RecorderError::PipelineInErrorState.status_code(), 500);
+        assert_eq!(RecorderError::PipelineLockError.status_code(), 500);
+    }
+
+    #[test]
+    fn error_log_carries_status_code_and_field_errors() {
+        let field_errors = vec![FieldError::new("output", "must not be empty")];
+        let log = RecorderErrorLog::from(RecorderError::ValidationError(field_errors.clone()));
+        assert_eq!(log.status_code, 400);
+        assert_eq!(log.field_errors, field_errors);
+
+        let log = RecorderErrorLog::from(RecorderError::SessionNotFound("s".into()));
+        assert_eq!(log.status_code, 404);
+        assert!(log.field_errors.is_empty());
+    }
+}
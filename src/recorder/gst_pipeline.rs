@@ -1,17 +1,27 @@
+use super::duration::DurationLimit;
+use super::pause::PauseGate;
+use super::pre_roll::PreRoll;
+use super::watchdog::FlowMonitor;
 use super::Config as RecorderConfig;
-use crate::recorder::errors::RecorderError;
+use crate::recorder::errors::{FieldError, RecorderError};
 use crate::traits::Pipeline;
 use crate::traits::{PipelineSink, PipelineSrc};
 use crate::{encoder, input, output};
+use crossbeam_channel::Receiver;
 use gst::prelude::*;
 use gstreamer as gst;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /* */
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum PipelineState {
     Stopped,
+    Starting,
+    Armed,
     Playing,
+    Paused,
+    Stopping,
     Error,
 }
 
@@ -19,7 +29,11 @@ impl std::fmt::Display for PipelineState {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             PipelineState::Stopped => write!(f, "Stopped"),
+            PipelineState::Starting => write!(f, "Starting"),
+            PipelineState::Armed => write!(f, "Armed"),
             PipelineState::Playing => write!(f, "Playing"),
+            PipelineState::Paused => write!(f, "Paused"),
+            PipelineState::Stopping => write!(f, "Stopping"),
             PipelineState::Error => write!(f, "Error"),
         }
     }
@@ -31,8 +45,28 @@ impl PipelineState {
         new_state: &PipelineState,
     ) -> anyhow::Result<PipelineState, RecorderError> {
         match (self, new_state) {
-            (PipelineState::Stopped, PipelineState::Playing) => Ok(PipelineState::Playing),
-            (PipelineState::Playing, PipelineState::Stopped) => Ok(PipelineState::Stopped),
+            (PipelineState::Stopped, PipelineState::Starting) => Ok(PipelineState::Starting),
+            (PipelineState::Starting, PipelineState::Playing) => Ok(PipelineState::Playing),
+            (PipelineState::Stopped, PipelineState::Armed) => Ok(PipelineState::Armed),
+            (PipelineState::Armed, PipelineState::Playing) => Ok(PipelineState::Playing),
+            (PipelineState::Playing, PipelineState::Paused) => Ok(PipelineState::Paused),
+            (PipelineState::Paused, PipelineState::Playing) => Ok(PipelineState::Playing),
+            (
+                PipelineState::Starting
+                | PipelineState::Armed
+                | PipelineState::Playing
+                | PipelineState::Paused,
+                PipelineState::Stopping,
+            ) => Ok(PipelineState::Stopping),
+            // A recording that reached its duration stops without a stop
+            // request.
+            (
+                PipelineState::Armed
+                | PipelineState::Playing
+                | PipelineState::Paused
+                | PipelineState::Stopping,
+                PipelineState::Stopped,
+            ) => Ok(PipelineState::Stopped),
             (_, PipelineState::Error) => Ok(PipelineState::Error),
             (PipelineState::Error, _) => Err(RecorderError::AppError(format!(
                 "Cannot transition from Error state {self:?} to {new_state:?})"
@@ -62,7 +96,16 @@ pub struct GstPipeline {
     pub state: PipelineState,
     input: input::Input,
     encoder: encoder::Encoder,
-    output: output::Output,
+    outputs: output::tee::Tee,
+    pause_gate: PauseGate,
+    pre_roll: Option<PreRoll>,
+    /// Running time the recording was started at, later than 0 if the
+    /// pipeline was armed first.
+    started_at: gst::ClockTime,
+    duration_limit: Option<DurationLimit>,
+    flow: FlowMonitor,
+    /// Set once the EOS went through all outputs.
+    pub eos_received: bool,
 }
 
 impl Drop for GstPipeline {
@@ -78,12 +121,54 @@ impl Drop for GstPipeline {
 }
 
 impl GstPipeline {
-    pub fn new(config: RecorderConfig) -> anyhow::Result<Self> {
+    pub fn new(mut config: RecorderConfig) -> anyhow::Result<Self> {
         let pipeline = gst::Pipeline::new(None);
 
+        // Pick an installed encoder before anything is linked, so a missing
+        // plugin is reported up front with the encoder that was asked for.
+        config.encoder = config.encoder.resolve()?;
+
         let input = input::Input::new(config.input.clone())?;
         let encoder = encoder::Encoder::new(config.encoder.clone())?;
-        let output = output::Output::new(config.output.clone())?;
+        let outputs =
+            output::tee::Tee::new(config.outputs.clone(), config.encoder.variant.codec())?;
+
+        let input_src = input.source().static_pad("src").ok_or_else(|| {
+            RecorderError::ElementError("Input has no src pad to pause".to_string())
+        })?;
+        // Ahead of the pause gate, a paused input still counts as running.
+        let flow = FlowMonitor::new(&input_src);
+        let pause_gate = PauseGate::new(&input_src);
+
+        let pre_roll = match config.pre_roll_seconds {
+            0 => None,
+            seconds => Some(PreRoll::new(seconds)?),
+        };
+
+        // Runs in the thread of the failing element before it returns the
+        // error flow, so the branch drops buffers before the error can reach
+        // the tee. The bus thread then detaches the branch.
+        let branches = outputs.handles();
+        if let Some(bus) = pipeline.bus() {
+            bus.set_sync_handler(move |_, msg| {
+                if let gst::MessageView::Error(err) = msg.view() {
+                    let branches = branches.lock().unwrap_or_else(|e| e.into_inner());
+                    let branch = err
+                        .src()
+                        .and_then(|src| branches.iter().find(|b| b.contains(&src)));
+                    if let Some(branch) = branch {
+                        tracing::warn!("Output {} failed", branch.name);
+                        branch.set_failed();
+                    }
+                }
+                gst::BusSyncReply::Pass
+            });
+        }
+
+        // The EOS of a single output is only posted by the pipeline when it
+        // forwards child messages. Needed to tell when a removed output is
+        // done.
+        pipeline.set_property("message-forward", true);
 
         Ok(Self {
             config,
@@ -91,7 +176,13 @@ impl GstPipeline {
             state: PipelineState::Stopped,
             input,
             encoder,
-            output,
+            outputs,
+            pause_gate,
+            pre_roll,
+            started_at: gst::ClockTime::ZERO,
+            duration_limit: None,
+            flow,
+            eos_received: false,
         })
     }
 
@@ -112,23 +203,289 @@ impl GstPipeline {
         }
     }
 
-    pub fn _get_state(&self) -> PipelineState {
+    pub fn get_state(&self) -> PipelineState {
         self.state
     }
 
+    /// Graphviz dot dump of the pipeline, e.g. to debug caps negotiation.
+    pub fn debug_graph(&self) -> String {
+        self.pipeline
+            .debug_to_dot_data(gst::DebugGraphDetails::all())
+            .to_string()
+    }
+
+    /// How long it has been recording, without the time it was paused.
+    pub fn running_time(&self) -> Option<gst::ClockTime> {
+        match self.state {
+            PipelineState::Playing | PipelineState::Paused => {
+                self.pipeline.current_running_time().map(|now| {
+                    now.saturating_sub(self.started_at)
+                        .saturating_sub(self.pause_gate.paused_time(now))
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Holds the encoded video back in the pre-roll buffer until `trigger`.
+    /// Called before the pipeline is started.
+    pub fn arm(&mut self) -> Result<(), RecorderError> {
+        let pre_roll = self.pre_roll.as_mut().ok_or_else(|| {
+            RecorderError::ConfigError("Arming needs pre_roll_seconds".to_string())
+        })?;
+        pre_roll
+            .arm()
+            .map_err(|e| RecorderError::ElementError(format!("Failed to arm: {e}")))?;
+        self.state.set_state(PipelineState::Armed)
+    }
+
+    /// Starts the recording of an armed pipeline, including the video in the
+    /// pre-roll buffer.
+    pub fn trigger(&mut self) -> Result<(), RecorderError> {
+        let pre_roll = match (self.state, self.pre_roll.as_mut()) {
+            (PipelineState::Armed, Some(pre_roll)) => pre_roll,
+            _ => {
+                return Err(RecorderError::StateError(
+                    "Only an armed recording can be triggered".to_string(),
+                ))
+            }
+        };
+        let now = self.pipeline.current_running_time().ok_or_else(|| {
+            RecorderError::StateError("Pipeline has no running time yet".to_string())
+        })?;
+        pre_roll
+            .trigger(now)
+            .map_err(|e| RecorderError::ElementError(format!("Failed to trigger: {e}")))?;
+        self.started_at = now;
+        self.limit_duration(Some(now))?;
+        self.state.set_state(PipelineState::Playing)
+    }
+
+    /// Ends the recording once it reaches `config.duration`, counted from
+    /// running time `start` or from the first frame.
+    fn limit_duration(&mut self, start: Option<gst::ClockTime>) -> Result<(), RecorderError> {
+        let duration = match self.config.duration {
+            Some(duration) if duration > 0 => gst::ClockTime::from_seconds(duration),
+            _ => return Ok(()),
+        };
+        let encoder_sink = self.encoder.sink().static_pad("sink").ok_or_else(|| {
+            RecorderError::ElementError("Encoder has no sink pad to limit".to_string())
+        })?;
+        self.duration_limit = Some(DurationLimit::new(&encoder_sink, duration, start));
+        Ok(())
+    }
+
+    /// Keeps the duration limit from ending a recording that is being
+    /// stopped.
+    pub fn cancel_duration(&self) {
+        if let Some(duration_limit) = &self.duration_limit {
+            duration_limit.cancel();
+        }
+    }
+
+    /// Whether the recording ended because it reached its duration.
+    pub fn completed_by_duration(&self) -> bool {
+        self.duration_limit
+            .as_ref()
+            .map(|duration_limit| duration_limit.completed())
+            .unwrap_or(false)
+    }
+
+    /// Lets an armed pipeline stop without recording anything.
+    pub fn disarm(&mut self) -> Result<(), RecorderError> {
+        if let Some(pre_roll) = self.pre_roll.as_mut() {
+            pre_roll
+                .disarm()
+                .map_err(|e| RecorderError::ElementError(format!("Failed to disarm: {e}")))?;
+        }
+        Ok(())
+    }
+
+    /// Stops recording without stopping the pipeline. The files stay open
+    /// and `resume` continues them.
+    pub fn pause(&mut self) -> Result<(), RecorderError> {
+        self.state.set_state(PipelineState::Paused)?;
+        self.pause_gate.pause();
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> Result<(), RecorderError> {
+        self.state.set_state(PipelineState::Playing)?;
+        self.pause_gate.resume();
+        Ok(())
+    }
+
+    /// Whether the pipeline is running, paused or not.
+    pub fn is_recording(&self) -> bool {
+        matches!(self.state, PipelineState::Playing | PipelineState::Paused)
+    }
+
+    /// When the input last delivered a buffer.
+    pub fn last_buffer(&self) -> Option<std::time::Instant> {
+        self.flow.last_buffer()
+    }
+
+    /// Lets the outputs finish their files after the pipeline got stuck, see
+    /// `output::tee::Tee::salvage`.
+    pub fn salvage_outputs(&self) {
+        self.outputs.salvage();
+    }
+
+    pub fn controls(&self) -> Result<Vec<input::Control>, RecorderError> {
+        self.input.controls()
+    }
+
+    /// Sets camera controls while recording. The config keeps the values, so
+    /// the status shows them.
+    pub fn set_controls(
+        &mut self,
+        values: &BTreeMap<String, i32>,
+    ) -> Result<Vec<input::Control>, RecorderError> {
+        let controls = self.input.set_controls(values)?;
+        if let input::Variant::V4l2(config) = &mut self.config.input.variant {
+            config.controls.extend(values.clone());
+        }
+        Ok(controls)
+    }
+
+    /// See `input::Input::segment_done`.
+    pub fn segment_done(&self) -> anyhow::Result<()> {
+        self.input.segment_done()
+    }
+
+    /// Flushes the outputs to storage, see `output::Output::fsync`.
+    pub fn fsync_outputs(&self) -> anyhow::Result<()> {
+        for output in self.outputs.outputs() {
+            output.fsync()?;
+        }
+        Ok(())
+    }
+
+    /// See `output::Output::fragment_closed`.
+    pub fn fragment_closed(&self, src: &gst::Object, location: &str) -> anyhow::Result<()> {
+        match self
+            .outputs
+            .branch_of(src)
+            .and_then(|branch| self.outputs.output(&branch.name))
+        {
+            Some(output) => output.fragment_closed(location),
+            None => Ok(()),
+        }
+    }
+
+    /// Detaches the output an error came from, as long as other outputs are
+    /// still running. Returns the name of the output, or `None` if the error
+    /// has to stop the whole pipeline.
+    pub fn isolate_error(&mut self, src: &gst::Object) -> Option<String> {
+        let branch = self.outputs.branch_of(src)?;
+        if self.outputs.is_detaching(&branch.name) {
+            // It is being removed anyway, don't wait for its EOS.
+            if let Err(e) = self.outputs.detach(&self.pipeline, &branch.name) {
+                tracing::error!("Failed to detach output {}: {e}", branch.name);
+            }
+            return Some(branch.name);
+        }
+        if self.outputs.output(&branch.name).is_none() {
+            // Further errors from a branch that is already gone.
+            return Some(branch.name);
+        }
+        if !self.outputs.has_healthy_branches() {
+            return None;
+        }
+        match self.outputs.detach(&self.pipeline, &branch.name) {
+            Ok(()) => {
+                self.config.outputs.retain(|o| o.name != branch.name);
+                Some(branch.name)
+            }
+            Err(e) => {
+                tracing::error!("Failed to detach output {}: {e}", branch.name);
+                None
+            }
+        }
+    }
+
+    /// Adds an output while the pipeline is playing.
+    pub fn add_output(&mut self, config: output::Config) -> Result<(), RecorderError> {
+        let mut errors = vec![];
+        config.validate("output", &mut errors);
+        let codec = self.config.encoder.variant.codec();
+        if !config.supports(codec) {
+            errors.push(FieldError::new(
+                "output",
+                format!("{codec} is not supported by output {}", config.name),
+            ));
+        }
+        if self.config.outputs.iter().any(|o| o.name == config.name) {
+            errors.push(FieldError::new(
+                "output.name",
+                format!("duplicate output name {}", config.name),
+            ));
+        }
+        if !errors.is_empty() {
+            return Err(RecorderError::ValidationError(errors));
+        }
+
+        self.outputs
+            .attach(&self.pipeline, config.clone())
+            .map_err(|e| match e.downcast::<RecorderError>() {
+                Ok(err) => err,
+                Err(e) => RecorderError::OutputError(format!("Failed to add output: {e}")),
+            })?;
+        self.config.outputs.push(config);
+        Ok(())
+    }
+
+    /// Starts removing an output while the pipeline is playing. The returned
+    /// channel fires once the output finished its file, see
+    /// `output_eos`.
+    pub fn remove_output(&mut self, name: &str) -> Result<Receiver<()>, RecorderError> {
+        if !self.config.outputs.iter().any(|o| o.name == name) {
+            return Err(RecorderError::OutputNotFound(name.to_string()));
+        }
+        if self.config.outputs.len() == 1 {
+            return Err(RecorderError::ConfigError(format!(
+                "Can't remove {name}, the last output. Stop the recording instead"
+            )));
+        }
+
+        let done = self.outputs.detach_gracefully(name).map_err(|e| {
+            match e.downcast::<RecorderError>() {
+                Ok(err) => err,
+                Err(e) => RecorderError::OutputError(format!("Failed to remove output: {e}")),
+            }
+        })?;
+        self.config.outputs.retain(|o| o.name != name);
+        Ok(done)
+    }
+
+    /// Removes an output that didn't finish after `remove_output` in time.
+    pub fn drop_output(&mut self, name: &str) -> anyhow::Result<()> {
+        self.outputs.detach(&self.pipeline, name)
+    }
+
+    /// Called for the EOS message of a single sink. Finishes the removal of
+    /// the output it belongs to, if it is being removed.
+    pub fn output_eos(&mut self, src: &gst::Object) -> anyhow::Result<()> {
+        self.outputs.finish_detach(&self.pipeline, src)?;
+        Ok(())
+    }
+
     pub fn link_pipelines(&self) -> anyhow::Result<()> {
         self.input.link(&self.pipeline.clone())?;
         self.encoder.link(&self.pipeline.clone())?;
-        self.output.link(&self.pipeline.clone())?;
+        if let Some(pre_roll) = &self.pre_roll {
+            pre_roll.link(&self.pipeline.clone())?;
+        }
+        self.outputs.link(&self.pipeline.clone())?;
 
         Ok(())
     }
 
-    pub fn connect_pipelines(&self) -> anyhow::Result<()> {
+    pub fn connect_pipelines(&mut self) -> anyhow::Result<()> {
         let input_src = self.input.source();
         let codec_sink = self.encoder.sink();
         let codec_src = self.encoder.source();
-        let output_sink = self.output.sink();
+        let output_sink = self.outputs.sink();
 
         println!("Input src: {:?}", input_src);
         println!("Codec sink: {:?}", codec_sink);
@@ -138,9 +495,33 @@ impl GstPipeline {
         input_src.link(&codec_sink).map_err(|e| {
             RecorderError::ElementError(format!("Error linking Input to Codec: {:?}", e))
         })?;
-        codec_src.link(&output_sink).map_err(|e| {
-            RecorderError::ElementError(format!("Error linking Codec to Output: {:?}", e))
-        })?;
+        match &self.pre_roll {
+            Some(pre_roll) => {
+                codec_src.link(&pre_roll.sink()).map_err(|e| {
+                    RecorderError::ElementError(format!(
+                        "Error linking Codec to pre-roll buffer: {:?}",
+                        e
+                    ))
+                })?;
+                pre_roll.source().link(&output_sink).map_err(|e| {
+                    RecorderError::ElementError(format!(
+                        "Error linking pre-roll buffer to Output: {:?}",
+                        e
+                    ))
+                })?;
+            }
+            None => {
+                codec_src.link(&output_sink).map_err(|e| {
+                    RecorderError::ElementError(format!("Error linking Codec to Output: {:?}", e))
+                })?;
+            }
+        }
+        self.outputs.connect()?;
+
+        // An armed pipeline counts from the trigger.
+        if self.pre_roll.is_none() {
+            self.limit_duration(None)?;
+        }
 
         Ok(())
     }
@@ -148,8 +529,123 @@ impl GstPipeline {
     pub fn unlink_pipelines(&self) -> anyhow::Result<()> {
         self.input.unlink(&self.pipeline)?;
         self.encoder.unlink(&self.pipeline)?;
-        self.output.unlink(&self.pipeline)?;
+        if let Some(pre_roll) = &self.pre_roll {
+            pre_roll.unlink(&self.pipeline)?;
+        }
+        self.outputs.unlink(&self.pipeline)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_resume_are_valid_while_recording() {
+        assert_eq!(
+            PipelineState::Playing
+                .is_valid_transition(&PipelineState::Paused)
+                .unwrap(),
+            PipelineState::Paused
+        );
+        assert_eq!(
+            PipelineState::Paused
+                .is_valid_transition(&PipelineState::Playing)
+                .unwrap(),
+            PipelineState::Playing
+        );
+        assert_eq!(
+            PipelineState::Paused
+                .is_valid_transition(&PipelineState::Stopping)
+                .unwrap(),
+            PipelineState::Stopping
+        );
+        assert_eq!(
+            PipelineState::Paused
+                .is_valid_transition(&PipelineState::Stopped)
+                .unwrap(),
+            PipelineState::Stopped
+        );
+    }
+
+    #[test]
+    fn pause_is_rejected_when_not_recording() {
+        for state in [
+            PipelineState::Stopped,
+            PipelineState::Starting,
+            PipelineState::Stopping,
+        ] {
+            assert!(matches!(
+                state.is_valid_transition(&PipelineState::Paused),
+                Err(RecorderError::AppError(_))
+            ));
+        }
+        assert!(matches!(
+            PipelineState::Stopped.is_valid_transition(&PipelineState::Playing),
+            Err(RecorderError::AppError(_))
+        ));
+    }
+
+    #[test]
+    fn armed_pre_roll_starts_or_stops() {
+        assert_eq!(
+            PipelineState::Stopped
+                .is_valid_transition(&PipelineState::Armed)
+                .unwrap(),
+            PipelineState::Armed
+        );
+        assert_eq!(
+            PipelineState::Armed
+                .is_valid_transition(&PipelineState::Playing)
+                .unwrap(),
+            PipelineState::Playing
+        );
+        assert_eq!(
+            PipelineState::Armed
+                .is_valid_transition(&PipelineState::Stopping)
+                .unwrap(),
+            PipelineState::Stopping
+        );
+        assert_eq!(
+            PipelineState::Armed
+                .is_valid_transition(&PipelineState::Stopped)
+                .unwrap(),
+            PipelineState::Stopped
+        );
+    }
+
+    #[test]
+    fn armed_is_only_reachable_from_stopped() {
+        for state in [
+            PipelineState::Starting,
+            PipelineState::Playing,
+            PipelineState::Paused,
+            PipelineState::Stopping,
+        ] {
+            assert!(matches!(
+                state.is_valid_transition(&PipelineState::Armed),
+                Err(RecorderError::AppError(_))
+            ));
+        }
+        assert!(matches!(
+            PipelineState::Armed.is_valid_transition(&PipelineState::Paused),
+            Err(RecorderError::AppError(_))
+        ));
+    }
+
+    #[test]
+    fn error_state_is_terminal() {
+        assert_eq!(
+            PipelineState::Paused
+                .is_valid_transition(&PipelineState::Error)
+                .unwrap(),
+            PipelineState::Error
+        );
+        assert!(matches!(
+            PipelineState::Error.is_valid_transition(&PipelineState::Playing),
+            Err(RecorderError::AppError(_))
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::util::gst_running_time;
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct GateState {
+    paused: bool,
+    /// Running time of the first buffer dropped by the current pause.
+    paused_at: Option<gst::ClockTime>,
+    /// Time removed from the recording by the pauses so far.
+    offset: gst::ClockTime,
+}
+
+/// Drops the raw video between `pause` and `resume`. The source keeps
+/// running, so resuming is instant. After a pause the running time is moved
+/// back by the time that was dropped, so the encoder and the outputs see one
+/// continuous stream.
+#[derive(Debug, Clone)]
+pub struct PauseGate {
+    state: Arc<Mutex<GateState>>,
+}
+
+impl PauseGate {
+    /// Installs the gate on `pad`, the source pad of the input.
+    pub fn new(pad: &gst::Pad) -> Self {
+        let state = Arc::new(Mutex::new(GateState::default()));
+
+        let gate_state = state.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            let buffer = match &info.data {
+                Some(gst::PadProbeData::Buffer(buffer)) => buffer,
+                _ => return gst::PadProbeReturn::Ok,
+            };
+
+            let mut state = gate_state.lock().unwrap_or_else(|e| e.into_inner());
+            if state.paused {
+                if state.paused_at.is_none() {
+                    state.paused_at = gst_running_time(pad, buffer);
+                }
+                return gst::PadProbeReturn::Drop;
+            }
+
+            // First buffer after a pause. It takes the place of the first
+            // buffer that was dropped.
+            if let Some(paused_at) = state.paused_at.take() {
+                if let Some(running_time) = gst_running_time(pad, buffer) {
+                    state.offset += running_time.saturating_sub(paused_at);
+                    tracing::info!("Resumed, {} removed from the recording", state.offset);
+                    pad.set_offset(-(state.offset.nseconds() as i64));
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        PauseGate { state }
+    }
+
+    pub fn pause(&self) {
+        self.lock().paused = true;
+    }
+
+    pub fn resume(&self) {
+        self.lock().paused = false;
+    }
+
+    /// Time removed from the recording at running time `now`, including the
+    /// current pause.
+    pub fn paused_time(&self, now: gst::ClockTime) -> gst::ClockTime {
+        let state = self.lock();
+        match state.paused_at {
+            Some(paused_at) => state.offset + now.saturating_sub(paused_at),
+            None => state.offset,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, GateState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,137 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use crate::recorder::errors::RecorderError;
+use crate::traits::*;
+use crate::util::{
+    gst_create_element, gst_running_time, gst_set_property, gst_set_property_from_str,
+};
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+
+/// Ring buffer of encoded video between the encoder and the outputs, so a
+/// recording can include the video from before it was started. While armed
+/// the outputs are held back and the queue drops its oldest buffers.
+#[derive(Debug)]
+pub struct PreRoll {
+    duration: gst::ClockTime,
+    queue: gst::Element,
+    block: Option<gst::PadProbeId>,
+}
+
+impl PreRoll {
+    pub fn new(seconds: u32) -> anyhow::Result<Self> {
+        tracing::info!("Creating {seconds}s pre-roll buffer");
+        let duration = gst::ClockTime::from_seconds(seconds as u64);
+
+        let queue = gst_create_element("queue", "pre_roll_queue")?;
+        gst_set_property(&queue, "max-size-buffers", 0u32)?;
+        gst_set_property(&queue, "max-size-bytes", 0u32)?;
+        gst_set_property(&queue, "max-size-time", duration.nseconds())?;
+        gst_set_property_from_str(&queue, "leaky", "downstream")?;
+
+        Ok(PreRoll {
+            duration,
+            queue,
+            block: None,
+        })
+    }
+
+    /// Holds the video back from the outputs until `trigger`.
+    pub fn arm(&mut self) -> anyhow::Result<()> {
+        let pad = self.src_pad()?;
+        self.block = pad.add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_, _| {
+            gst::PadProbeReturn::Ok
+        });
+        Ok(())
+    }
+
+    /// Lets the video through to the outputs, starting at the first keyframe
+    /// of the last `duration` before `now`. The outputs see it start at 0.
+    pub fn trigger(&mut self, now: gst::ClockTime) -> anyhow::Result<()> {
+        let pad = self.src_pad()?;
+        let start = now.saturating_sub(self.duration);
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            let buffer = match &info.data {
+                Some(gst::PadProbeData::Buffer(buffer)) => buffer,
+                _ => return gst::PadProbeReturn::Ok,
+            };
+            if buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+                return gst::PadProbeReturn::Drop;
+            }
+            match gst_running_time(pad, buffer) {
+                Some(running_time) if running_time >= start => {
+                    tracing::info!(
+                        "Recording starts {} before the trigger",
+                        now.saturating_sub(running_time)
+                    );
+                    pad.set_offset(-(running_time.nseconds() as i64));
+                    gst::PadProbeReturn::Remove
+                }
+                _ => gst::PadProbeReturn::Drop,
+            }
+        });
+        self.unblock(&pad);
+        Ok(())
+    }
+
+    /// Drops the held back video, so an EOS can reach the outputs without
+    /// anything being recorded.
+    pub fn disarm(&mut self) -> anyhow::Result<()> {
+        let pad = self.src_pad()?;
+        pad.add_probe(gst::PadProbeType::BUFFER, |_, _| gst::PadProbeReturn::Drop);
+        self.unblock(&pad);
+        Ok(())
+    }
+
+    fn unblock(&mut self, pad: &gst::Pad) {
+        if let Some(block) = self.block.take() {
+            pad.remove_probe(block);
+        }
+    }
+
+    fn src_pad(&self) -> anyhow::Result<gst::Pad> {
+        Ok(self.queue.static_pad("src").ok_or_else(|| {
+            RecorderError::ElementError("Pre-roll queue has no src pad".to_string())
+        })?)
+    }
+}
+
+impl Drop for PreRoll {
+    fn drop(&mut self) {
+        tracing::info!("Dropping pre-roll buffer");
+    }
+}
+
+impl Pipeline for PreRoll {
+    fn link(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Linking pre-roll buffer");
+        pipeline.add(&self.queue).map_err(|e| {
+            RecorderError::ElementError(format!("Failed to add pre-roll queue to pipeline: {e}"))
+        })?;
+
+        Ok(())
+    }
+
+    fn unlink(&self, pipeline: &gst::Pipeline) -> anyhow::Result<()> {
+        tracing::info!("Unlinking pre-roll buffer");
+        pipeline.remove(&self.queue).map_err(|e| {
+            RecorderError::ElementError(format!(
+                "Failed to remove pre-roll queue from pipeline: {e}"
+            ))
+        })?;
+
+        Ok(())
+    }
+}
+
+impl PipelineSrc for PreRoll {
+    fn source(&self) -> gst::Element {
+        self.queue.clone()
+    }
+}
+
+impl PipelineSink for PreRoll {
+    fn sink(&self) -> gst::Element {
+        self.queue.clone()
+    }
+}
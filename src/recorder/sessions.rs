@@ -0,0 +1,141 @@
+use super::errors::RecorderError;
+use super::{Config, PipelineState, Recorder, Status};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, TryLockError};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SessionStatus {
+    pub id: String,
+    #[serde(flatten)]
+    pub status: Status,
+}
+
+/// Registry of recording sessions keyed by `recorder::Config::name`, or by
+/// a generated id when the name is empty. Each session owns its own
+/// `Recorder`, and with it its own `GstPipeline` and bus-watch thread.
+#[derive(Default)]
+pub struct Sessions {
+    sessions: HashMap<String, Arc<Mutex<Recorder>>>,
+    next_id: u64,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the session for `config`. A stopped session with the same id
+    /// is replaced, a running one is an error.
+    pub fn create(
+        &mut self,
+        config: &Config,
+    ) -> Result<(String, Arc<Mutex<Recorder>>), RecorderError> {
+        let id = if config.name.is_empty() {
+            self.generate_id()
+        } else {
+            config.name.clone()
+        };
+
+        if let Some(existing) = self.sessions.get(&id) {
+            // Called with the registry locked, so a busy recorder, e.g. one
+            // that is starting, counts as running instead of being waited on.
+            let state = match existing.try_lock() {
+                Ok(recorder) => recorder.get_state()?,
+                Err(TryLockError::WouldBlock) => return Err(RecorderError::SessionExists(id)),
+                Err(TryLockError::Poisoned(e)) => {
+                    return Err(RecorderError::AppError(format!(
+                        "Failed to lock recorder: {e}"
+                    )))
+                }
+            };
+            if state != PipelineState::Stopped && state != PipelineState::Error {
+                return Err(RecorderError::SessionExists(id));
+            }
+        }
+
+        let recorder = Arc::new(Mutex::new(Recorder::new()?));
+        self.sessions.insert(id.clone(), recorder.clone());
+        Ok((id, recorder))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Arc<Mutex<Recorder>>, RecorderError> {
+        self.sessions
+            .get(id)
+            .cloned()
+            .ok_or_else(|| RecorderError::SessionNotFound(id.to_string()))
+    }
+
+    /// Removes the session `id`, unless it was replaced by another
+    /// `recorder` in the meantime.
+    pub fn remove(&mut self, id: &str, recorder: &Arc<Mutex<Recorder>>) {
+        if let Some(existing) = self.sessions.get(id) {
+            if Arc::ptr_eq(existing, recorder) {
+                self.sessions.remove(id);
+            }
+        }
+    }
+
+    /// Every session, sorted by id. Lock the recorders only after the
+    /// registry is released, so one busy recorder doesn't hold up requests
+    /// for all the others.
+    pub fn all(&self) -> Vec<(String, Arc<Mutex<Recorder>>)> {
+        let mut sessions: Vec<_> = self
+            .sessions
+            .iter()
+            .map(|(id, recorder)| (id.clone(), recorder.clone()))
+            .collect();
+        sessions.sort_by(|a, b| a.0.cmp(&b.0));
+        sessions
+    }
+
+    fn generate_id(&mut self) -> String {
+        loop {
+            self.next_id += 1;
+            let id = format!("session-{}", self.next_id);
+            if !self.sessions.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+}
+
+pub fn status(id: &str, recorder: &Arc<Mutex<Recorder>>) -> Result<SessionStatus, RecorderError> {
+    let status = lock_recorder(recorder)?.status()?;
+    Ok(SessionStatus {
+        id: id.to_string(),
+        status,
+    })
+}
+
+/// The status of each of `sessions`, see `Sessions::all`.
+pub fn list(
+    sessions: &[(String, Arc<Mutex<Recorder>>)],
+) -> Result<Vec<SessionStatus>, RecorderError> {
+    sessions
+        .iter()
+        .map(|(id, recorder)| status(id, recorder))
+        .collect()
+}
+
+/// The one of `sessions` that is recording from the input `name`.
+pub fn with_input(
+    sessions: &[(String, Arc<Mutex<Recorder>>)],
+    name: &str,
+) -> Result<Option<Arc<Mutex<Recorder>>>, RecorderError> {
+    for (_, recorder) in sessions {
+        if lock_recorder(recorder)?.has_input(name)? {
+            return Ok(Some(recorder.clone()));
+        }
+    }
+    Ok(None)
+}
+
+pub fn lock_recorder(
+    recorder: &Arc<Mutex<Recorder>>,
+) -> Result<std::sync::MutexGuard<'_, Recorder>, RecorderError> {
+    recorder.lock().map_err(|e| {
+        tracing::error!("Failed to lock recorder: {e}");
+        RecorderError::AppError(format!("Failed to lock recorder: {e}"))
+    })
+}
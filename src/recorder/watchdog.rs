@@ -0,0 +1,222 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+use super::errors::{RecorderError, RecorderErrorLog};
+use super::gst_pipeline::{GstPipeline, PipelineState};
+use super::{Recorder, StopReason};
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// How often the watchdog checks the pipeline.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long the outputs get to finish their files after the watchdog
+/// tripped.
+const SALVAGE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Name of the application message that ends the bus thread of a pipeline
+/// the watchdog gave up on.
+pub const RELEASE_MESSAGE: &str = "recorder-watchdog-release";
+
+/// Timeouts in seconds. 0 turns a check off.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Time the pipeline has to reach PLAYING after a start.
+    pub start_timeout_seconds: u64,
+    /// Time the EOS has to go through the pipeline after a stop.
+    pub stop_timeout_seconds: u64,
+    /// Time without a buffer coming out of the input while recording.
+    pub stall_timeout_seconds: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            start_timeout_seconds: 10,
+            stop_timeout_seconds: 30,
+            stall_timeout_seconds: 5,
+        }
+    }
+}
+
+impl Config {
+    pub fn start_timeout(&self) -> Option<Duration> {
+        timeout(self.start_timeout_seconds)
+    }
+
+    pub fn stop_timeout(&self) -> Option<Duration> {
+        timeout(self.stop_timeout_seconds)
+    }
+
+    pub fn stall_timeout(&self) -> Option<Duration> {
+        timeout(self.stall_timeout_seconds)
+    }
+}
+
+fn timeout(seconds: u64) -> Option<Duration> {
+    match seconds {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    }
+}
+
+/// Remembers when the last buffer came out of the input.
+#[derive(Debug, Clone)]
+pub struct FlowMonitor {
+    last_buffer: Arc<Mutex<Option<Instant>>>,
+}
+
+impl FlowMonitor {
+    /// Installs the monitor on `pad`, the source pad of the input. It has to
+    /// be installed before the pause gate, which drops buffers.
+    pub fn new(pad: &gst::Pad) -> Self {
+        let last_buffer = Arc::new(Mutex::new(None));
+
+        let probe_last_buffer = last_buffer.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            *probe_last_buffer.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+            gst::PadProbeReturn::Ok
+        });
+
+        FlowMonitor { last_buffer }
+    }
+
+    pub fn last_buffer(&self) -> Option<Instant> {
+        *self.last_buffer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Watches a pipeline for a start or stop that doesn't finish, and for an
+/// input that stopped delivering buffers. When that happens the pipeline is
+/// moved to the error state, the outputs get a chance to finish their files
+/// and the pipeline is set to NULL.
+pub struct Watchdog {
+    config: Config,
+    pipeline: Arc<Mutex<GstPipeline>>,
+    state_tx: Arc<watch::Sender<PipelineState>>,
+    last_error: Arc<Mutex<Option<RecorderErrorLog>>>,
+    stop_reason: Arc<Mutex<Option<StopReason>>>,
+}
+
+impl Watchdog {
+    pub fn new(
+        config: Config,
+        pipeline: Arc<Mutex<GstPipeline>>,
+        state_tx: Arc<watch::Sender<PipelineState>>,
+        last_error: Arc<Mutex<Option<RecorderErrorLog>>>,
+        stop_reason: Arc<Mutex<Option<StopReason>>>,
+    ) -> Self {
+        Watchdog {
+            config,
+            pipeline,
+            state_tx,
+            last_error,
+            stop_reason,
+        }
+    }
+
+    pub fn spawn(self) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || self.run())
+    }
+
+    fn run(self) {
+        tracing::info!("Watchdog started");
+        let mut state = PipelineState::Stopped;
+        let mut since = Instant::now();
+
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+
+            let (current, last_buffer) = match self.pipeline.lock() {
+                Ok(gst_pipeline) => (gst_pipeline.get_state(), gst_pipeline.last_buffer()),
+                Err(e) => {
+                    tracing::error!("Watchdog failed to lock pipeline: {e}");
+                    return;
+                }
+            };
+            if current != state {
+                state = current;
+                since = Instant::now();
+            }
+
+            let reason = match state {
+                PipelineState::Stopped | PipelineState::Error => break,
+                PipelineState::Starting => self
+                    .config
+                    .start_timeout()
+                    .filter(|timeout| since.elapsed() > *timeout)
+                    .map(|timeout| format!("PLAYING not reached within {}s", timeout.as_secs())),
+                PipelineState::Stopping => self
+                    .config
+                    .stop_timeout()
+                    .filter(|timeout| since.elapsed() > *timeout)
+                    .map(|timeout| format!("EOS not received within {}s", timeout.as_secs())),
+                PipelineState::Armed | PipelineState::Playing | PipelineState::Paused => {
+                    let flowing_since = last_buffer.filter(|t| *t > since).unwrap_or(since);
+                    self.config
+                        .stall_timeout()
+                        .filter(|timeout| flowing_since.elapsed() > *timeout)
+                        .map(|timeout| {
+                            format!("No buffers from the input for {}s", timeout.as_secs())
+                        })
+                }
+            };
+
+            if let Some(reason) = reason {
+                self.trip(state, reason);
+                break;
+            }
+        }
+        tracing::info!("Watchdog stopped");
+    }
+
+    fn trip(&self, state: PipelineState, reason: String) {
+        tracing::error!("Watchdog: {reason} while {state}");
+        let pipeline = {
+            let mut gst_pipeline = match self.pipeline.lock() {
+                Ok(gst_pipeline) => gst_pipeline,
+                Err(e) => {
+                    tracing::error!("Watchdog failed to lock pipeline: {e}");
+                    return;
+                }
+            };
+            let err = RecorderError::Timeout(format!("{reason} while {state}"));
+            let log = Recorder::error_log(&mut gst_pipeline, err);
+            Recorder::store_error(&self.last_error, &log);
+            Recorder::store_stop_reason(&self.stop_reason, Some(StopReason::Error));
+            self.state_tx.send_replace(PipelineState::Error);
+
+            gst_pipeline.salvage_outputs();
+            gst_pipeline.pipeline.clone()
+        };
+
+        // The bus thread finishes the files and releases the pipeline if the
+        // EOS makes it through the outputs.
+        let deadline = Instant::now() + SALVAGE_TIMEOUT;
+        while Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+            match self.pipeline.lock() {
+                Ok(gst_pipeline) if gst_pipeline.eos_received => {
+                    tracing::info!("Watchdog: outputs finished");
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("Watchdog failed to lock pipeline: {e}");
+                    return;
+                }
+            }
+        }
+
+        tracing::error!("Watchdog: outputs did not finish, forcing the pipeline to NULL");
+        let release = gst::message::Application::new(gst::Structure::new_empty(RELEASE_MESSAGE));
+        if let Err(e) = pipeline.post_message(release) {
+            tracing::error!("Failed to post {RELEASE_MESSAGE}: {e}");
+        }
+        if let Err(e) = pipeline.set_state(gst::State::Null) {
+            tracing::error!("Failed to set pipeline to NULL: {e}");
+        }
+    }
+}
@@ -1,74 +1,64 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
-use crate::recorder::errors::RecorderErrorLog;
+mod operations;
+
+use crate::config::ServerConfig;
+use crate::input::devices::{Devices, InUse};
+use crate::output::Config as OutputConfig;
+use crate::recorder::errors::{RecorderError, RecorderErrorLog};
+use crate::recorder::sessions::{self, lock_recorder, Sessions};
 use crate::recorder::Config as RecorderConfig;
 use crate::recorder::Recorder;
+use crate::recorder::Transition;
 use async_std::task::sleep;
 use axum::{
     body::{boxed, Body, BoxBody},
-    extract::{Json, State},
+    extract::{Json, Path, Query, State},
     http::{HeaderValue, Request, Response, StatusCode, Uri},
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
 use gstreamer::tags::TrackCount;
-use std::net::IpAddr;
-use std::pin::Pin;
+use operations::{lock_operations, Operations};
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 struct AppState {
     pub recorder: Arc<Mutex<Recorder>>,
-    pub ip_addr: IpAddr,
-    pub port: u16,
+    pub sessions: Arc<Mutex<Sessions>>,
+    pub operations: Arc<Mutex<Operations>>,
+    pub devices: Arc<Devices>,
+    pub config: Arc<ServerConfig>,
+}
+
+/// `?wait=true` blocks until a start or stop is done, instead of returning
+/// an operation id right away.
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+struct WaitParams {
+    wait: bool,
 }
 
 impl AppState {
-    fn new(recorder: Arc<Mutex<Recorder>>, ip_addr: IpAddr, port: u16) -> Self {
+    fn new(recorder: Arc<Mutex<Recorder>>, config: ServerConfig) -> Self {
         Self {
             recorder,
-            ip_addr,
-            port,
+            sessions: Arc::new(Mutex::new(Sessions::new())),
+            operations: Arc::new(Mutex::new(Operations::new())),
+            devices: Arc::new(Devices::new()),
+            config: Arc::new(config),
         }
     }
 }
 
-type BoxedFuture<'a> = Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>>;
-
-async fn run_timer<F>(duration: u64, closure: F)
-where
-    F: FnOnce() -> BoxedFuture<'static> + Send + 'static,
-{
-    tracing::info!("Running timer for {duration} seconds");
-    tokio::time::sleep(tokio::time::Duration::from_secs(duration)).await;
-    closure().await;
-}
-
-async fn start_timer(duration: u64, host: String, port: u16) {
-    tracing::info!("Starting timer for {duration} seconds");
-    run_timer(duration, move || {
-        Box::pin(async move {
-            tracing::info!("Timer expired. Stopping recorder");
-            let client = reqwest::Client::new();
-            let url = format!("http://{host}:{port}/stop");
-            let res = client.post(url).body("duration timer").send().await;
-            match res {
-                Ok(res) => {
-                    tracing::info!("{:?}", res);
-                }
-                Err(e) => {
-                    tracing::error!("Error: {:?}", e);
-                }
-            }
-        })
-    })
-    .await;
-}
-
 impl IntoResponse for RecorderErrorLog {
     fn into_response(self) -> Response<BoxBody> {
+        let status_code =
+            StatusCode::from_u16(self.status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
         match serde_json::to_string(&self) {
-            Ok(body) => (StatusCode::INTERNAL_SERVER_ERROR, body).into_response(),
+            Ok(body) => (status_code, body).into_response(),
             Err(e) => {
                 tracing::error!("failed to serialize error: {e}");
                 (
@@ -82,12 +72,11 @@ impl IntoResponse for RecorderErrorLog {
 }
 
 pub async fn start_server(
-    host: IpAddr,
-    port: u16,
+    config: ServerConfig,
     shutdown_signal: Option<tokio::sync::oneshot::Receiver<()>>,
 ) -> anyhow::Result<()> {
     tracing::info!("Starting server");
-    let host_addr = std::net::SocketAddr::from((host, port));
+    let host_addr = std::net::SocketAddr::from((config.host, config.port));
 
     let recorder = match Recorder::new() {
         Ok(recorder) => Arc::new(Mutex::new(recorder)),
@@ -96,12 +85,27 @@ pub async fn start_server(
         }
     };
 
-    let app_state = AppState::new(recorder, host, port);
+    let app_state = AppState::new(recorder, config);
 
     let app = Router::new()
         .route("/", get(root))
         .route("/start", post(start))
         .route("/stop", post(stop))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/status", get(status))
+        .route("/presets", get(list_presets))
+        .route("/sessions", get(list_sessions).post(create_session))
+        .route("/sessions/:id", get(session_status))
+        .route("/sessions/:id/start", post(start_session))
+        .route("/sessions/:id/stop", post(stop_session))
+        .route("/sessions/:id/pause", post(pause_session))
+        .route("/sessions/:id/resume", post(resume_session))
+        .route("/sessions/:id/outputs", post(add_session_output))
+        .route("/sessions/:id/outputs/:name", delete(remove_session_output))
+        .route("/operations/:id", get(operation_status))
+        .route("/devices", get(list_devices))
+        .route("/inputs/:name/controls", get(controls).put(set_controls))
         .with_state(app_state);
 
     tracing::debug!("listening on {}", host_addr);
@@ -136,40 +140,358 @@ fn get_recorder(state: &AppState) -> std::sync::MutexGuard<'_, Recorder> {
         .unwrap()
 }
 
+fn get_sessions(state: &AppState) -> std::sync::MutexGuard<'_, Sessions> {
+    state
+        .sessions
+        .lock()
+        .map_err(|e| panic!("failed to lock sessions: {e}"))
+        .unwrap()
+}
+
 async fn root(State(_): State<AppState>) -> String {
     tracing::info!("/");
     format!("Recorder {:?}", crate::VERSION)
 }
 
+/// Waits for `transition` with `?wait=true`, otherwise returns 202 Accepted
+/// with the id of an operation that tracks it.
+async fn respond_to_transition(
+    state: &AppState,
+    params: WaitParams,
+    kind: &str,
+    session: Option<String>,
+    transition: Transition,
+) -> Result<Response<BoxBody>, RecorderErrorLog> {
+    respond_to_transition_with(
+        state,
+        params,
+        kind,
+        session,
+        transition,
+        serde_json::json!({}),
+    )
+    .await
+}
+
+/// Like `respond_to_transition`, with the fields of `extra` added to the
+/// response.
+async fn respond_to_transition_with(
+    state: &AppState,
+    params: WaitParams,
+    kind: &str,
+    session: Option<String>,
+    transition: Transition,
+    mut extra: serde_json::Value,
+) -> Result<Response<BoxBody>, RecorderErrorLog> {
+    if let Some(id) = &session {
+        extra["id"] = serde_json::json!(id);
+    }
+    if params.wait {
+        transition.wait().await?;
+        extra["status"] = serde_json::json!("OK");
+        return Ok((StatusCode::OK, extra.to_string()).into_response());
+    }
+
+    let operation = operations::spawn(&state.operations, kind, session, transition);
+    extra["status"] = serde_json::json!("Accepted");
+    extra["operation"] = serde_json::json!(operation);
+    Ok((StatusCode::ACCEPTED, extra.to_string()).into_response())
+}
+
 async fn start(
     State(state): State<AppState>,
-    Json(payload): Json<RecorderConfig>,
+    Query(params): Query<WaitParams>,
+    Json(request): Json<serde_json::Value>,
 ) -> Result<impl IntoResponse, RecorderErrorLog> {
+    let payload = state.config.resolve(request)?;
     tracing::info!("/start: {:?}", payload);
-    let duration = payload.duration.unwrap_or(0);
+    let transition = get_recorder(&state).start(payload)?;
+
+    respond_to_transition(&state, params, "start", None, transition).await
+}
+
+async fn stop(
+    State(state): State<AppState>,
+    Query(params): Query<WaitParams>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("/stop");
+    let transition = get_recorder(&state).stop()?;
+
+    respond_to_transition(&state, params, "stop", None, transition).await
+}
+
+async fn pause(State(state): State<AppState>) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("/pause");
     let mut recorder = get_recorder(&state);
-    recorder.start(payload)?;
-
-    // There is probably a better way to do the duration. Look into GST for this.
-    if duration > 0 {
-        let _ = tokio::task::spawn(start_timer(
-            duration,
-            state.ip_addr.to_string(),
-            state.port.clone(),
-        ));
-    }
+
+    recorder.pause()?;
 
     let response = serde_json::json!({"status": "OK"}).to_string();
     Ok((StatusCode::OK, response).into_response())
 }
 
-async fn stop(State(state): State<AppState>) -> Result<impl IntoResponse, RecorderErrorLog> {
-    tracing::info!("/stop");
+async fn resume(State(state): State<AppState>) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("/resume");
     let mut recorder = get_recorder(&state);
 
-    recorder.stop()?;
+    recorder.resume()?;
 
-    tracing::info!("recorder stopped");
     let response = serde_json::json!({"status": "OK"}).to_string();
     Ok((StatusCode::OK, response).into_response())
 }
+
+async fn status(State(state): State<AppState>) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("/status");
+    let recorder = get_recorder(&state);
+
+    let status = recorder.status()?;
+
+    let response = serde_json::to_string(&status)
+        .map_err(|e| RecorderError::AppError(format!("Failed to serialize status: {e}")))?;
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn list_presets(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("GET /presets");
+    let presets = state
+        .config
+        .presets
+        .keys()
+        .map(|name| Ok((name.clone(), state.config.preset(name)?)))
+        .collect::<Result<std::collections::BTreeMap<String, RecorderConfig>, RecorderError>>()?;
+
+    let response = serde_json::to_string(&presets)
+        .map_err(|e| RecorderError::AppError(format!("Failed to serialize presets: {e}")))?;
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn list_sessions(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("GET /sessions");
+    let sessions = get_sessions(&state).all();
+    let sessions = sessions::list(&sessions)?;
+
+    let response = serde_json::to_string(&sessions)
+        .map_err(|e| RecorderError::AppError(format!("Failed to serialize sessions: {e}")))?;
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn create_session(
+    State(state): State<AppState>,
+    Query(params): Query<WaitParams>,
+    Json(request): Json<serde_json::Value>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    let payload = state.config.resolve(request)?;
+    tracing::info!("POST /sessions: {:?}", payload);
+
+    // Hold the session lock until the session is started so a concurrent
+    // request for the same id sees it running.
+    let (id, transition) = {
+        let mut sessions = get_sessions(&state);
+        let (id, session) = sessions.create(&payload)?;
+        let mut recorder = lock_recorder(&session)?;
+        std::mem::drop(sessions);
+
+        let started = recorder.start(payload);
+        std::mem::drop(recorder);
+        match started {
+            Ok(transition) => (id, transition),
+            Err(e) => {
+                // A session that never started must not stay listed, or
+                // keep its name taken.
+                get_sessions(&state).remove(&id, &session);
+                return Err(e);
+            }
+        }
+    };
+
+    respond_to_transition(&state, params, "start", Some(id), transition).await
+}
+
+async fn start_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("POST /sessions/{id}/start");
+    let session = get_sessions(&state).get(&id)?;
+    let mut recorder = lock_recorder(&session)?;
+
+    recorder.trigger()?;
+
+    let response = serde_json::json!({"status": "OK", "id": id}).to_string();
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn session_status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("GET /sessions/{id}");
+    let session = get_sessions(&state).get(&id)?;
+    let status = sessions::status(&id, &session)?;
+
+    let response = serde_json::to_string(&status)
+        .map_err(|e| RecorderError::AppError(format!("Failed to serialize status: {e}")))?;
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn stop_session(
+    State(state): State<AppState>,
+    Query(params): Query<WaitParams>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("POST /sessions/{id}/stop");
+    let session = get_sessions(&state).get(&id)?;
+    let transition = lock_recorder(&session)?.stop()?;
+
+    respond_to_transition(&state, params, "stop", Some(id), transition).await
+}
+
+async fn operation_status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("GET /operations/{id}");
+    let operation = lock_operations(&state.operations).get(&id)?;
+
+    let response = serde_json::to_string(&operation)
+        .map_err(|e| RecorderError::AppError(format!("Failed to serialize operation: {e}")))?;
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn list_devices(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("GET /devices");
+    let mut in_use = vec![];
+    if let Some(path) = get_recorder(&state).status()?.device() {
+        in_use.push(InUse {
+            path: path.to_string(),
+            session: None,
+        });
+    }
+    let sessions = get_sessions(&state).all();
+    for session in sessions::list(&sessions)? {
+        if let Some(path) = session.status.device() {
+            in_use.push(InUse {
+                path: path.to_string(),
+                session: Some(session.id.clone()),
+            });
+        }
+    }
+    let devices = state.devices.list(&in_use)?;
+
+    let response = serde_json::to_string(&devices)
+        .map_err(|e| RecorderError::AppError(format!("Failed to serialize devices: {e}")))?;
+    Ok((StatusCode::OK, response).into_response())
+}
+
+/// The recorder, `/start` or a session, that is recording from the input
+/// `name`.
+fn recorder_with_input(
+    state: &AppState,
+    name: &str,
+) -> Result<Arc<Mutex<Recorder>>, RecorderError> {
+    if get_recorder(state).has_input(name)? {
+        return Ok(state.recorder.clone());
+    }
+    let sessions = get_sessions(state).all();
+    sessions::with_input(&sessions, name)?
+        .ok_or_else(|| RecorderError::InputNotFound(name.to_string()))
+}
+
+async fn controls(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("GET /inputs/{name}/controls");
+    let recorder = recorder_with_input(&state, &name)?;
+    let controls = lock_recorder(&recorder)?.controls()?;
+
+    let response = serde_json::to_string(&controls)
+        .map_err(|e| RecorderError::AppError(format!("Failed to serialize controls: {e}")))?;
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn set_controls(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(values): Json<BTreeMap<String, i32>>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("PUT /inputs/{name}/controls: {:?}", values);
+    let recorder = recorder_with_input(&state, &name)?;
+    let controls = lock_recorder(&recorder)?.set_controls(&values)?;
+
+    let response = serde_json::to_string(&controls)
+        .map_err(|e| RecorderError::AppError(format!("Failed to serialize controls: {e}")))?;
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn pause_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("POST /sessions/{id}/pause");
+    let session = get_sessions(&state).get(&id)?;
+    let mut recorder = lock_recorder(&session)?;
+
+    recorder.pause()?;
+
+    let response = serde_json::json!({"status": "OK", "id": id}).to_string();
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn resume_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("POST /sessions/{id}/resume");
+    let session = get_sessions(&state).get(&id)?;
+    let mut recorder = lock_recorder(&session)?;
+
+    recorder.resume()?;
+
+    let response = serde_json::json!({"status": "OK", "id": id}).to_string();
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn add_session_output(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(output): Json<OutputConfig>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("POST /sessions/{id}/outputs: {:?}", output);
+    let name = output.name.clone();
+    let session = get_sessions(&state).get(&id)?;
+    let mut recorder = lock_recorder(&session)?;
+
+    recorder.add_output(output)?;
+
+    let response = serde_json::json!({"status": "OK", "id": id, "output": name}).to_string();
+    Ok((StatusCode::OK, response).into_response())
+}
+
+async fn remove_session_output(
+    State(state): State<AppState>,
+    Path((id, name)): Path<(String, String)>,
+    Query(params): Query<WaitParams>,
+) -> Result<impl IntoResponse, RecorderErrorLog> {
+    tracing::info!("DELETE /sessions/{id}/outputs/{name}");
+    let session = get_sessions(&state).get(&id)?;
+    // The recorder must not stay locked while the output finishes.
+    let transition = lock_recorder(&session)?.remove_output(&name)?;
+
+    let output = serde_json::json!({ "output": name });
+    respond_to_transition_with(
+        &state,
+        params,
+        "remove_output",
+        Some(id),
+        transition,
+        output,
+    )
+    .await
+}
@@ -0,0 +1,112 @@
+use crate::recorder::errors::{RecorderError, RecorderErrorLog};
+use crate::recorder::Transition;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Finished operations kept around for `GET /operations/{id}`.
+const MAX_FINISHED: usize = 100;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum OperationState {
+    Pending,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Operation {
+    pub id: String,
+    /// "start", "stop" or "remove_output".
+    pub kind: String,
+    /// Session the operation belongs to. `None` for `/start` and `/stop`.
+    pub session: Option<String>,
+    pub state: OperationState,
+    pub error: Option<RecorderErrorLog>,
+}
+
+/// Starts, stops and output removals that were accepted but may still be
+/// running in the pipeline.
+#[derive(Default)]
+pub struct Operations {
+    operations: HashMap<String, Operation>,
+    finished: VecDeque<String>,
+    next_id: u64,
+}
+
+impl Operations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &str) -> Result<Operation, RecorderError> {
+        self.operations
+            .get(id)
+            .cloned()
+            .ok_or_else(|| RecorderError::OperationNotFound(id.to_string()))
+    }
+
+    fn create(&mut self, kind: &str, session: Option<String>) -> String {
+        self.next_id += 1;
+        let id = format!("op-{}", self.next_id);
+        self.operations.insert(
+            id.clone(),
+            Operation {
+                id: id.clone(),
+                kind: kind.to_string(),
+                session,
+                state: OperationState::Pending,
+                error: None,
+            },
+        );
+        id
+    }
+
+    fn finish(&mut self, id: &str, result: Result<(), RecorderErrorLog>) {
+        if let Some(operation) = self.operations.get_mut(id) {
+            match result {
+                Ok(()) => operation.state = OperationState::Done,
+                Err(error) => {
+                    operation.state = OperationState::Failed;
+                    operation.error = Some(error);
+                }
+            }
+        }
+
+        self.finished.push_back(id.to_string());
+        while self.finished.len() > MAX_FINISHED {
+            if let Some(old) = self.finished.pop_front() {
+                self.operations.remove(&old);
+            }
+        }
+    }
+}
+
+/// Waits for `transition` in the background. Returns the id of the
+/// operation that tracks it.
+pub fn spawn(
+    operations: &Arc<Mutex<Operations>>,
+    kind: &str,
+    session: Option<String>,
+    transition: Transition,
+) -> String {
+    let id = lock_operations(operations).create(kind, session);
+    tracing::info!("Operation {id}: {kind}");
+
+    let operations = operations.clone();
+    let operation_id = id.clone();
+    tokio::spawn(async move {
+        let result = transition.wait().await;
+        if let Err(e) = &result {
+            tracing::error!("Operation {operation_id} failed: {:?}", e.error_message);
+        }
+        lock_operations(&operations).finish(&operation_id, result);
+    });
+    id
+}
+
+pub fn lock_operations(
+    operations: &Arc<Mutex<Operations>>,
+) -> std::sync::MutexGuard<'_, Operations> {
+    operations.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -1,10 +1,21 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 use crate::encoder::x264enc::{self, Config};
-use crate::encoder::{x265enc, VideoEncoder as VideoEncoderConfig};
+use crate::encoder::{
+    av1enc, nvh264enc, nvh265enc, vaapih264enc, vaapih265enc, vp8enc, vp9enc, x265enc,
+    Codec, VideoEncoder as VideoEncoderConfig,
+};
+use crate::output::filesink;
+use crate::output::muxer::{
+    Config as MuxerConfig, FlvConfig, MatroskaConfig, Mp4Config, MpegTsConfig, WebmConfig,
+};
 use crate::recorder::errors::RecorderError;
 use gstreamer as gst;
 use gstreamer::glib::error;
+use gstreamer::glib::translate::{ToGlibPtr, ToGlibPtrMut};
 use gstreamer::prelude::*;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 
 pub fn gst_create_element(element_type: &str, name: &str) -> anyhow::Result<gst::Element> {
     gst::ElementFactory::make(element_type)
@@ -17,6 +28,127 @@ pub fn gst_create_element(element_type: &str, name: &str) -> anyhow::Result<gst:
         })
 }
 
+/// Brings elements that were just added to a bin to the bin's state, so
+/// they can be added to a running pipeline. Sinks go first, so no element
+/// pushes into one that isn't ready yet.
+pub fn gst_sync_state_with_parent(elements: &[gst::Element]) -> anyhow::Result<()> {
+    for element in elements.iter().rev() {
+        element.sync_state_with_parent().map_err(|e| {
+            RecorderError::ElementError(format!(
+                "Failed to sync state of {} with pipeline: {e}",
+                element.name()
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// Sets elements that were removed from a running pipeline to `Null`, so
+/// they release their resources.
+pub fn gst_set_null(elements: &[gst::Element]) {
+    for element in elements {
+        if let Err(e) = element.set_state(gst::State::Null) {
+            tracing::warn!("Failed to set {} to Null: {e}", element.name());
+        }
+    }
+}
+
+/// Running time of `buffer` on `pad`, based on the pad's current segment.
+pub fn gst_running_time(pad: &gst::Pad, buffer: &gst::BufferRef) -> Option<gst::ClockTime> {
+    pad.sticky_event::<gst::event::Segment>(0)
+        .and_then(|event| {
+            event
+                .segment()
+                .downcast_ref::<gst::ClockTime>()
+                .and_then(|segment| segment.to_running_time(buffer.pts()))
+        })
+}
+
+/// One line per format, size and framerate of `caps`, e.g.
+/// `video/x-raw YUY2 640x480 30/1`.
+pub fn gst_describe_caps(caps: &gst::Caps) -> Vec<String> {
+    caps.iter()
+        .map(|s| {
+            let field = |name: &str| {
+                s.value(name)
+                    .ok()
+                    .and_then(|v| v.serialize().ok())
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "*".to_string())
+            };
+            let format = match s.has_field("format") {
+                true => format!(" {}", field("format")),
+                false => String::new(),
+            };
+            format!(
+                "{}{format} {}x{} {}",
+                s.name(),
+                field("width"),
+                field("height"),
+                field("framerate")
+            )
+        })
+        .collect()
+}
+
+/// Checks that the file at `path` can be written, or created if it doesn't
+/// exist yet. Nothing on disk is touched.
+pub fn check_writable(path: &Path) -> Result<(), String> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match std::fs::metadata(parent) {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => return Err(format!("{} is not a directory", parent.display())),
+        Err(e) => return Err(format!("{}: {e}", parent.display())),
+    }
+
+    if path.is_dir() {
+        return Err(format!("{} is a directory", path.display()));
+    }
+
+    if path.exists() {
+        access(path, libc::W_OK).map_err(|e| format!("{} is not writable: {e}", path.display()))
+    } else {
+        access(parent, libc::W_OK | libc::X_OK)
+            .map_err(|e| format!("{} is not writable: {e}", parent.display()))
+    }
+}
+
+/// Checks that files can be created in `dir`, without creating any.
+pub fn check_dir_writable(dir: &Path) -> Result<(), String> {
+    match std::fs::metadata(dir) {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => return Err(format!("{} is not a directory", dir.display())),
+        Err(e) => return Err(format!("{}: {e}", dir.display())),
+    }
+
+    access(dir, libc::W_OK | libc::X_OK)
+        .map_err(|e| format!("{} is not writable: {e}", dir.display()))
+}
+
+/// `access(2)`, whether the process has the permissions in `mode` on `path`.
+fn access(path: &Path, mode: libc::c_int) -> std::io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: `path` is a NUL terminated string that outlives the call.
+    match unsafe { libc::access(path.as_ptr(), mode) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Flushes a file written by another handle, e.g. the one of a filesink, to
+/// storage.
+pub fn fsync_file(path: &Path) -> anyhow::Result<()> {
+    std::fs::File::open(path)
+        .and_then(|file| file.sync_all())
+        .map_err(|e| {
+            RecorderError::OutputError(format!("Failed to fsync {}: {e}", path.display())).into()
+        })
+}
+
 pub fn _gst_link_elements(src: &gst::Element, sink: &gst::Element) -> anyhow::Result<()> {
     src.link(sink).map_err(|_| {
         anyhow::Error::new(RecorderError::ElementError(format!(
@@ -27,32 +159,238 @@ pub fn _gst_link_elements(src: &gst::Element, sink: &gst::Element) -> anyhow::Re
 }
 
 pub fn gst_create_video_encoder(config: &VideoEncoderConfig) -> anyhow::Result<gst::Element> {
+    let element = config.element_name()?;
+    let encoder = match gst_create_element(&element, "video_encoder") {
+        Ok(element) => element,
+        Err(_) => anyhow::bail!("Error creating {element}"),
+    };
+
     match config {
-        VideoEncoderConfig::X264(config) => {
-            let encoder = match gst_create_element("x264enc", "video_encoder") {
-                Ok(element) => element,
-                Err(_) => anyhow::bail!("Error creating x264enc"),
-            };
-            set_x264_props(&encoder, config);
-            Ok(encoder)
+        VideoEncoderConfig::X264(config) => set_x264_props(&encoder, config),
+        VideoEncoderConfig::NVH264(config) => set_nvh264_props(&encoder, config)?,
+        VideoEncoderConfig::VAAPIH264(config) => set_vaapih264_props(&encoder, config)?,
+        VideoEncoderConfig::X265(config) => set_x265_props(&encoder, config),
+        VideoEncoderConfig::NVH265(config) => set_nvh265_props(&encoder, config)?,
+        VideoEncoderConfig::VAAPIH265(config) => set_vaapih265_props(&encoder, config)?,
+        VideoEncoderConfig::AV1(config) => set_av1_props(&encoder, &element, config)?,
+        VideoEncoderConfig::VP8(config) => set_vp8_props(&encoder, config)?,
+        VideoEncoderConfig::VP9(config) => set_vp9_props(&encoder, config)?,
+    }
+    Ok(encoder)
+}
+
+/// Parser for the start of an output branch, after its queue. Each muxer
+/// wants its own stream format, e.g. byte-stream for MPEG-TS and avc for
+/// MP4, and the tee can only hand every branch the same one. `None` for
+/// codecs whose muxers take the encoder output as is.
+pub fn gst_create_parser(codec: Codec, name: &str) -> anyhow::Result<Option<gst::Element>> {
+    let element = match codec {
+        Codec::H264 => "h264parse",
+        Codec::H265 => "h265parse",
+        Codec::AV1 | Codec::VP8 | Codec::VP9 => return Ok(None),
+    };
+    let parser = gst_create_element(element, name)?;
+    // Repeat SPS/PPS with every keyframe, so each segment or fragment can be
+    // decoded on its own.
+    gst_set_property(&parser, "config-interval", -1i32)?;
+    Ok(Some(parser))
+}
+
+pub fn gst_create_muxer(config: &MuxerConfig, name: &str) -> anyhow::Result<gst::Element> {
+    let muxer = gst_create_element(config.name(), name)?;
+
+    match config {
+        MuxerConfig::Flv(config) => set_flvmux_props(&muxer, config)?,
+        MuxerConfig::Mpeg4(config) => set_mp4mux_props(&muxer, config)?,
+        MuxerConfig::MpegTs(config) => set_mpegtsmux_props(&muxer, config)?,
+        MuxerConfig::Matroska(config) => set_matroskamux_props(&muxer, config)?,
+        MuxerConfig::Webm(config) => set_webmmux_props(&muxer, config)?,
+    }
+    Ok(muxer)
+}
+
+/// Sets `name` on `element`, converting `value` to the type the property is
+/// declared with. Plugins disagree on e.g. `gint` vs `guint` for the same
+/// setting, and setting a value of the wrong type would panic.
+pub fn gst_set_property(
+    element: &gst::Element,
+    name: &str,
+    value: impl ToValue,
+) -> anyhow::Result<()> {
+    let pspec = element.find_property(name).ok_or_else(|| {
+        RecorderError::ElementError(format!("{} has no property {name}", element.name()))
+    })?;
+    let mut value = value
+        .to_value()
+        .transform_with_type(pspec.value_type())
+        .map_err(|e| {
+            RecorderError::ElementError(format!(
+                "Invalid value for {} property {name}: {e}",
+                element.name()
+            ))
+        })?;
+
+    // GObject only logs a critical for a value outside the property's range
+    // and keeps the old value. `g_param_value_validate` clamps the value and
+    // reports whether it had to.
+    let modified = unsafe {
+        gst::glib::gobject_ffi::g_param_value_validate(
+            pspec.to_glib_none().0,
+            value.to_glib_none_mut().0,
+        )
+    };
+    if modified != 0 {
+        return Err(RecorderError::ElementError(format!(
+            "Value out of range for {} property {name}",
+            element.name()
+        ))
+        .into());
+    }
+
+    element.set_property_from_value(name, &value);
+    Ok(())
+}
+
+/// Like `set_property_from_str`, but returns an error instead of panicking
+/// when the element doesn't have the property or `value` isn't valid for it,
+/// e.g. an enum nick an older plugin version doesn't know.
+pub fn gst_set_property_from_str(
+    element: &gst::Element,
+    name: &str,
+    value: &str,
+) -> anyhow::Result<()> {
+    let pspec = element.find_property(name).ok_or_else(|| {
+        RecorderError::ElementError(format!("{} has no property {name}", element.name()))
+    })?;
+    let value = gst::glib::Value::deserialize(value, pspec.value_type()).map_err(|_| {
+        RecorderError::ElementError(format!(
+            "Invalid value {value} for {} property {name}",
+            element.name()
+        ))
+    })?;
+    element.set_property_from_value(name, &value);
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/nvcodec/nvh264enc.html?gi-language=c
+// More info: 'gst-inspect-1.0 nvh264enc'
+pub fn set_nvh264_props(
+    nvh264: &gst::Element,
+    config: &nvh264enc::NvH264Config,
+) -> anyhow::Result<()> {
+    gst_set_property(nvh264, "bitrate", config.bitrate)?;
+    gst_set_property_from_str(nvh264, "preset", &config.preset)?;
+    gst_set_property_from_str(nvh264, "rc-mode", &config.rc_mode)?;
+    gst_set_property(nvh264, "gop-size", config.gop_size)?;
+    gst_set_property(nvh264, "zerolatency", config.zerolatency)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/nvcodec/nvh265enc.html?gi-language=c
+// More info: 'gst-inspect-1.0 nvh265enc'
+pub fn set_nvh265_props(
+    nvh265: &gst::Element,
+    config: &nvh265enc::NvH265Config,
+) -> anyhow::Result<()> {
+    gst_set_property(nvh265, "bitrate", config.bitrate)?;
+    gst_set_property_from_str(nvh265, "preset", &config.preset)?;
+    gst_set_property_from_str(nvh265, "rc-mode", &config.rc_mode)?;
+    gst_set_property(nvh265, "gop-size", config.gop_size)?;
+    gst_set_property(nvh265, "zerolatency", config.zerolatency)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/vaapi/vaapih264enc.html?gi-language=c
+// More info: 'gst-inspect-1.0 vaapih264enc'
+pub fn set_vaapih264_props(
+    vaapih264: &gst::Element,
+    config: &vaapih264enc::VaapiH264Config,
+) -> anyhow::Result<()> {
+    gst_set_property(vaapih264, "bitrate", config.bitrate)?;
+    gst_set_property_from_str(vaapih264, "rate-control", &config.rate_control)?;
+    gst_set_property(vaapih264, "keyframe-period", config.keyframe_period)?;
+    gst_set_property(vaapih264, "quality-level", config.quality_level)?;
+    gst_set_property(vaapih264, "max-bframes", config.max_bframes)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/vaapi/vaapih265enc.html?gi-language=c
+// More info: 'gst-inspect-1.0 vaapih265enc'
+pub fn set_vaapih265_props(
+    vaapih265: &gst::Element,
+    config: &vaapih265enc::VaapiH265Config,
+) -> anyhow::Result<()> {
+    gst_set_property(vaapih265, "bitrate", config.bitrate)?;
+    gst_set_property_from_str(vaapih265, "rate-control", &config.rate_control)?;
+    gst_set_property(vaapih265, "keyframe-period", config.keyframe_period)?;
+    gst_set_property(vaapih265, "quality-level", config.quality_level)?;
+    gst_set_property(vaapih265, "max-bframes", config.max_bframes)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/rav1e/index.html?gi-language=c
+// https://gstreamer.freedesktop.org/documentation/svtav1/index.html?gi-language=c
+// https://gstreamer.freedesktop.org/documentation/aom/aomenc.html?gi-language=c
+// More info: 'gst-inspect-1.0 rav1enc' etc.
+pub fn set_av1_props(
+    av1: &gst::Element,
+    element: &str,
+    config: &av1enc::Config,
+) -> anyhow::Result<()> {
+    match element {
+        "rav1enc" => {
+            gst_set_property(av1, "bitrate", config.bitrate.saturating_mul(1000))?;
+            gst_set_property(av1, "speed-preset", config.speed.min(10))?;
+            gst_set_property(av1, "max-key-frame-interval", config.key_int_max)?;
+            gst_set_property(av1, "tile-cols", config.tile_columns)?;
+            gst_set_property(av1, "tile-rows", config.tile_rows)?;
         }
-        VideoEncoderConfig::X265(config) => {
-            let encoder = match gst_create_element("x265enc", "video_encoder") {
-                Ok(element) => element,
-                Err(_) => anyhow::bail!("Error creating x265enc"),
-            };
-            set_x265_props(&encoder, config);
-            Ok(encoder)
+        "svtav1enc" => {
+            gst_set_property(av1, "target-bitrate", config.bitrate)?;
+            gst_set_property(av1, "preset", config.speed.min(13))?;
+            gst_set_property(av1, "intra-period-length", config.key_int_max)?;
+            gst_set_property(
+                av1,
+                "parameters-string",
+                format!(
+                    "tile-columns={}:tile-rows={}",
+                    config.tile_columns, config.tile_rows
+                ),
+            )?;
         }
-        VideoEncoderConfig::AV1(config) => {
-            let encoder = match gst_create_element("av1enc", "video_encoder") {
-                Ok(element) => element,
-                Err(_) => anyhow::bail!("Error creating av1enc"),
-            };
-            Ok(encoder)
+        "aomenc" => {
+            gst_set_property(av1, "target-bitrate", config.bitrate)?;
+            gst_set_property(av1, "cpu-used", config.speed.min(8))?;
+            gst_set_property(av1, "keyframe-max-dist", config.key_int_max)?;
+            gst_set_property(av1, "tile-columns", config.tile_columns)?;
+            gst_set_property(av1, "tile-rows", config.tile_rows)?;
+            gst_set_property(av1, "row-mt", true)?;
         }
-        _ => anyhow::bail!("Encoder not implemented"),
+        _ => anyhow::bail!("Unknown AV1 encoder {element}"),
     }
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/vpx/vp8enc.html?gi-language=c
+// More info: 'gst-inspect-1.0 vp8enc'
+pub fn set_vp8_props(vp8: &gst::Element, config: &vp8enc::Config) -> anyhow::Result<()> {
+    gst_set_property(vp8, "target-bitrate", config.target_bitrate)?;
+    gst_set_property(vp8, "deadline", config.deadline)?;
+    gst_set_property(vp8, "cpu-used", config.cpu_used)?;
+    gst_set_property(vp8, "keyframe-max-dist", config.keyframe_max_dist)?;
+    gst_set_property(vp8, "threads", config.threads)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/vpx/vp9enc.html?gi-language=c
+// More info: 'gst-inspect-1.0 vp9enc'
+pub fn set_vp9_props(vp9: &gst::Element, config: &vp9enc::Config) -> anyhow::Result<()> {
+    gst_set_property(vp9, "target-bitrate", config.target_bitrate)?;
+    gst_set_property(vp9, "deadline", config.deadline)?;
+    gst_set_property(vp9, "cpu-used", config.cpu_used)?;
+    gst_set_property(vp9, "keyframe-max-dist", config.keyframe_max_dist)?;
+    gst_set_property(vp9, "threads", config.threads)?;
+    Ok(())
 }
 
 // https://gstreamer.freedesktop.org/documentation/x265/index.html?gi-language=c
@@ -114,3 +452,126 @@ pub fn set_x264_props(x264: &gst::Element, config: &x264enc::Config) {
     x264.set_property("vbv-buf-capacity", config.vbv_buffer_capacity);
     x264.set_property("weightb", config.weightb);
 }
+
+// https://gstreamer.freedesktop.org/documentation/isomp4/mp4mux.html?gi-language=c
+// More info: 'gst-inspect-1.0 mp4mux'
+pub fn set_mp4mux_props(mp4mux: &gst::Element, config: &Mp4Config) -> anyhow::Result<()> {
+    gst_set_property(mp4mux, "faststart", config.faststart)?;
+    // Left unset, mp4mux creates its own temporary file.
+    if !config.faststart_file.is_empty() {
+        gst_set_property(mp4mux, "faststart-file", &config.faststart_file)?;
+    }
+    gst_set_property(mp4mux, "force-chunks", config.force_chunks)?;
+    gst_set_property(
+        mp4mux,
+        "force-create-timecode-trak",
+        config.force_create_timecode_track,
+    )?;
+    gst_set_property(mp4mux, "fragment-duration", config.fragment_duration)?;
+    gst_set_property_from_str(mp4mux, "fragment-mode", config.fragment_mode.nick())?;
+    gst_set_property(mp4mux, "interleave-bytes", config.interleave_bytes)?;
+    gst_set_property(mp4mux, "interleave-time", config.interleave_time)?;
+    gst_set_property(mp4mux, "latency", config.latency)?;
+    gst_set_property(mp4mux, "max-raw-audio-drift", config.max_raw_audio_drift)?;
+    gst_set_property(mp4mux, "min-upstream-latency", config.min_upstream_latency)?;
+    if !config.moov_recovery_file.is_empty() {
+        gst_set_property(mp4mux, "moov-recovery-file", &config.moov_recovery_file)?;
+    }
+    gst_set_property(mp4mux, "movie-timescale", config.movie_timescale)?;
+    gst_set_property(mp4mux, "presentation-time", config.presentation_time)?;
+    gst_set_property(
+        mp4mux,
+        "reserved-bytes-per-sec",
+        config.reserved_bytes_per_sec,
+    )?;
+    gst_set_property(
+        mp4mux,
+        "reserved-max-duration",
+        config.reserved_max_duration,
+    )?;
+    gst_set_property(
+        mp4mux,
+        "reserved-moov-update-period",
+        config.reserved_moov_update_period,
+    )?;
+    gst_set_property(mp4mux, "reserved-prefill", config.reserved_prefill)?;
+    gst_set_property(mp4mux, "start-gap-threshold", config.start_gap_threshold)?;
+    gst_set_property(mp4mux, "start-time", config.start_time)?;
+    gst_set_property_from_str(
+        mp4mux,
+        "start-time-selection",
+        config.start_time_selection.nick(),
+    )?;
+    gst_set_property(mp4mux, "streamable", config.streamable)?;
+    gst_set_property(mp4mux, "trak-timescale", config.track_timescale)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/flv/flvmux.html?gi-language=c
+// More info: 'gst-inspect-1.0 flvmux'
+pub fn set_flvmux_props(flvmux: &gst::Element, config: &FlvConfig) -> anyhow::Result<()> {
+    gst_set_property(flvmux, "latency", config.latency)?;
+    gst_set_property(flvmux, "start-time", config.start_time)?;
+    gst_set_property(flvmux, "streamable", config.streamable)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/mpegtsmux/mpegtsmux.html?gi-language=c
+// More info: 'gst-inspect-1.0 mpegtsmux'
+pub fn set_mpegtsmux_props(mpegtsmux: &gst::Element, config: &MpegTsConfig) -> anyhow::Result<()> {
+    gst_set_property(mpegtsmux, "alignment", config.alignment)?;
+    gst_set_property(mpegtsmux, "bitrate", config.bitrate)?;
+    gst_set_property(mpegtsmux, "latency", config.latency)?;
+    gst_set_property(
+        mpegtsmux,
+        "min-upstream-latency",
+        config.min_upstream_latency,
+    )?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/matroska/matroskamux.html?gi-language=c
+// More info: 'gst-inspect-1.0 matroskamux'
+pub fn set_matroskamux_props(
+    matroskamux: &gst::Element,
+    config: &MatroskaConfig,
+) -> anyhow::Result<()> {
+    gst_set_property(matroskamux, "streamable", config.streamable)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/matroska/webmmux.html?gi-language=c
+// More info: 'gst-inspect-1.0 webmmux'
+pub fn set_webmmux_props(webmmux: &gst::Element, config: &WebmConfig) -> anyhow::Result<()> {
+    gst_set_property(webmmux, "streamable", config.streamable)?;
+    Ok(())
+}
+
+// https://gstreamer.freedesktop.org/documentation/coreelements/filesink.html?gi-language=c
+// More info: 'gst-inspect-1.0 filesink'
+pub fn set_filesink_props(
+    filesink: &gst::Element,
+    config: &filesink::Config,
+) -> anyhow::Result<()> {
+    gst_set_property(filesink, "location", &config.location)?;
+    gst_set_property(filesink, "append", config.append)?;
+    gst_set_property(filesink, "async", config.async_to_pause)?;
+    gst_set_property(filesink, "blocksize", config.blocksize)?;
+    gst_set_property_from_str(filesink, "buffer-mode", config.buffer_mode.nick())?;
+    gst_set_property(filesink, "buffer-size", config.buffer_size)?;
+    gst_set_property(filesink, "max-bitrate", config.max_bitrate)?;
+    gst_set_property(filesink, "max-lateness", config.max_lateness)?;
+    gst_set_property(
+        filesink,
+        "max-transient-error-timeout",
+        config.max_transient_error_timeout,
+    )?;
+    gst_set_property(filesink, "o-sync", config.o_sync)?;
+    gst_set_property(filesink, "processing-deadline", config.processing_deadline)?;
+    gst_set_property(filesink, "qos", config.qos)?;
+    gst_set_property(filesink, "render-delay", config.render_delay)?;
+    gst_set_property(filesink, "sync", config.sync)?;
+    gst_set_property(filesink, "throttle-time", config.throttle_time)?;
+    gst_set_property(filesink, "ts-offset", config.ts_offset)?;
+    Ok(())
+}